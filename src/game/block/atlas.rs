@@ -0,0 +1,244 @@
+use image::RgbaImage;
+use std::collections::HashMap;
+use util::TexRect;
+
+use super::*;
+
+/// All of the block textures packed into one image
+pub struct Atlas {
+    pub image: RgbaImage,
+    /// Position and size of every texture in the atlas, in pixels
+    placements: HashMap<String, (u32, u32, u32, u32)>,
+    pub animations: Vec<Animation>,
+}
+
+/// A texture made of frames stacked on top of each other, which are copied into the atlas one
+/// at a time
+pub struct Animation {
+    /// Position of the texture's padding in the atlas, in pixels
+    pub x: u32,
+    pub y: u32,
+    /// Every image the animation shows with its padding, followed by its smaller mip levels
+    ///
+    /// Blended frames get a few images in between, so nothing has to be computed while
+    /// animating.
+    pub images: Vec<Vec<RgbaImage>>,
+    /// How long each image is shown, in seconds
+    image_time: f32,
+}
+
+impl Animation {
+    /// How many images a blended frame is split into
+    const BLEND_STEPS: usize = 8;
+
+    fn new(x: u32, y: u32, frames: Vec<RgbaImage>, frame_time: f32, interpolate: bool) -> Self {
+        let steps = if interpolate { Self::BLEND_STEPS } else { 1 };
+        let images = (0..frames.len() * steps)
+            .map(|i| {
+                let (frame, step) = (i / steps, i % steps);
+                let mut img = frames[frame].clone();
+                if step > 0 {
+                    let t = step as f32 / steps as f32;
+                    let next = &frames[(frame + 1) % frames.len()];
+                    for (p, q) in img.pixels_mut().zip(next.pixels()) {
+                        for c in 0..4 {
+                            p.0[c] = (p.0[c] as f32 * (1.0 - t) + q.0[c] as f32 * t) as u8;
+                        }
+                    }
+                }
+                mip_levels(pad(&img))
+            })
+            .collect();
+        Self {
+            x,
+            y,
+            images,
+            image_time: frame_time / steps as f32,
+        }
+    }
+
+    /// Index of the image shown at the given time
+    pub fn image(&self, time: f32) -> usize {
+        (time / self.image_time) as usize % self.images.len()
+    }
+}
+
+/// Surrounds a texture with its edges, and extends it to the next texel of the smallest mip level
+fn pad(img: &RgbaImage) -> RgbaImage {
+    const P: u32 = Atlas::PADDING;
+    let (w, h) = (img.width(), img.height());
+    RgbaImage::from_fn(align(w + 2 * P), align(h + 2 * P), |i, j| {
+        // the padding repeats the closest pixel on the edge
+        let sx = (i as i32 - P as i32).clamp(0, w as i32 - 1) as u32;
+        let sy = (j as i32 - P as i32).clamp(0, h as i32 - 1) as u32;
+        *img.get_pixel(sx, sy)
+    })
+}
+
+/// Rounds a size up to a whole texel of the smallest mip level
+fn align(x: u32) -> u32 {
+    x.next_multiple_of(1 << Atlas::MIP_LEVELS)
+}
+
+/// The image followed by its mip levels, every one half as large as the one before it
+fn mip_levels(img: RgbaImage) -> Vec<RgbaImage> {
+    let mut levels = vec![img];
+    for _ in 0..Atlas::MIP_LEVELS {
+        let last = levels.last().unwrap();
+        let next = RgbaImage::from_fn(last.width() / 2, last.height() / 2, |x, y| {
+            let mut sum = [0u32; 4];
+            for (i, j) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let p = last.get_pixel(x * 2 + i, y * 2 + j);
+                for (s, c) in sum.iter_mut().zip(p.0) {
+                    *s += c as u32;
+                }
+            }
+            image::Rgba(sum.map(|x| (x / 4) as u8))
+        });
+        levels.push(next);
+    }
+    levels
+}
+
+impl Atlas {
+    /// Space around every texture, filled with the texture's edges so that the neighboring
+    /// textures don't bleed in
+    pub const PADDING: u32 = 2;
+    /// How many smaller mip levels the atlas can have before the padding is too thin to keep
+    /// the textures apart
+    pub const MIP_LEVELS: u32 = Atlas::PADDING.trailing_zeros();
+
+    /// Packs the textures into rows, tallest first
+    ///
+    /// The space taken up by every texture is rounded up so that the textures start on a texel
+    /// of the smallest mip level
+    pub fn new(images: Vec<(String, RgbaImage)>) -> Self {
+        const P: u32 = Atlas::PADDING;
+        let mut images = images;
+        images.sort_by_key(|(_, x)| std::cmp::Reverse((x.height(), x.width())));
+
+        let area: u32 = images
+            .iter()
+            .map(|(_, x)| align(x.width() + 2 * P) * align(x.height() + 2 * P))
+            .sum();
+        let widest = images.iter().map(|(_, x)| align(x.width() + 2 * P)).max();
+        let width = ((area as f32).sqrt().ceil() as u32)
+            .max(widest.unwrap_or(1))
+            .next_power_of_two();
+
+        // place the textures
+        let mut placements = HashMap::new();
+        let (mut x, mut y, mut row_height) = (0, 0, 0);
+        for (name, img) in &images {
+            let (w, h) = (align(img.width() + 2 * P), align(img.height() + 2 * P));
+            if x + w > width {
+                x = 0;
+                y += row_height;
+                row_height = 0;
+            }
+            placements.insert(name.clone(), (x + P, y + P, img.width(), img.height()));
+            x += w;
+            row_height = row_height.max(h);
+        }
+        let height = (y + row_height).max(1).next_power_of_two();
+
+        // copy them into the atlas
+        let mut image = RgbaImage::new(width, height);
+        for (name, img) in &images {
+            let (px, py, _, _) = placements[name];
+            let padded = pad(img);
+            for (i, j, p) in padded.enumerate_pixels() {
+                image.put_pixel(px - P + i, py - P + j, *p);
+            }
+        }
+        Atlas {
+            image,
+            placements,
+            animations: Vec::new(),
+        }
+    }
+
+    /// Animates a texture which is already in the atlas, the frames have to be the same size as
+    /// the texture
+    pub fn add_animation(
+        &mut self,
+        name: &str,
+        frames: Vec<RgbaImage>,
+        frame_time: f32,
+        interpolate: bool,
+    ) {
+        let (x, y, _, _) = self.placements[name];
+        self.animations.push(Animation::new(
+            x - Self::PADDING,
+            y - Self::PADDING,
+            frames,
+            frame_time,
+            interpolate,
+        ));
+    }
+
+    /// Texture coordinates of a texture, or of a rectangle of pixels inside of it
+    ///
+    /// None if the texture hasn't been loaded or the rectangle doesn't fit into the texture
+    pub fn rect(&self, name: &str, pixels: Option<(i32, i32, i32, i32)>) -> Option<TexRect> {
+        let (x, y, w, h) = *self.placements.get(name)?;
+        let (left, top, right, bottom) = pixels.unwrap_or((0, 0, w as i32, h as i32));
+        let fits = |a: i32, max: u32| a >= 0 && a <= max as i32;
+        if !(fits(left, w) && fits(right, w) && fits(top, h) && fits(bottom, h)) {
+            return None;
+        }
+        let (aw, ah) = (self.image.width() as f32, self.image.height() as f32);
+        Some(TexRect {
+            left: (x as i32 + left) as f32 / aw,
+            top: (y as i32 + top) as f32 / ah,
+            right: (x as i32 + right) as f32 / aw,
+            bottom: (y as i32 + bottom) as f32 / ah,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(value: u8) -> RgbaImage {
+        RgbaImage::from_pixel(4, 4, image::Rgba([value, value, value, 255]))
+    }
+
+    #[test]
+    fn animation_images() {
+        let a = Animation::new(0, 0, vec![frame(0), frame(80)], 1.0, false);
+        assert_eq!(a.images.len(), 2);
+        assert_eq!((a.image(0.5), a.image(1.5), a.image(2.5)), (0, 1, 0));
+        // every image is padded and followed by its smaller mip levels
+        let size = align(4 + 2 * Atlas::PADDING);
+        let sizes: Vec<u32> = a.images[1].iter().map(|x| x.width()).collect();
+        let expected: Vec<u32> = (0..=Atlas::MIP_LEVELS).map(|x| size >> x).collect();
+        assert_eq!(sizes, expected);
+        assert!(a.images[1].iter().all(|x| x.get_pixel(0, 0).0[0] == 80));
+    }
+
+    #[test]
+    fn interpolated_animation_blends() {
+        let a = Animation::new(0, 0, vec![frame(0), frame(80)], 1.0, true);
+        assert_eq!(a.images.len(), 2 * Animation::BLEND_STEPS);
+        let value = |i: usize| a.images[i][0].get_pixel(2, 2).0[0];
+        assert_eq!(value(0), 0);
+        assert_eq!(value(Animation::BLEND_STEPS / 2), 40);
+        assert_eq!(value(Animation::BLEND_STEPS), 80);
+        // the last frame blends back into the first one
+        assert_eq!(value(Animation::BLEND_STEPS * 3 / 2), 40);
+        assert_eq!(a.image(1.0 / Animation::BLEND_STEPS as f32 * 1.5), 1);
+    }
+
+    #[test]
+    fn padding_repeats_the_edges() {
+        let mut img = frame(0);
+        img.put_pixel(0, 0, image::Rgba([255, 0, 0, 255]));
+        let padded = pad(&img);
+        assert_eq!(padded.get_pixel(0, 0).0, [255, 0, 0, 255]);
+        let p = Atlas::PADDING;
+        assert_eq!(padded.get_pixel(p, p).0, [255, 0, 0, 255]);
+        assert_eq!(padded.get_pixel(p + 1, p).0, [0, 0, 0, 255]);
+    }
+}
@@ -0,0 +1,168 @@
+use super::*;
+use world::ChunkServer;
+
+/// Custom logic of a block type, registered by name with `BlockManager::register_behavior`
+///
+/// Every hook has a default which does what a plain block does.
+pub trait BlockBehavior: Sync + Send {
+    /// Whether the block can be placed at a position, in the given state
+    fn can_place_at(&self, world: &mut ChunkServer, pos: BlockPos, state: BlockState) -> bool {
+        let _ = (world, pos, state);
+        true
+    }
+
+    /// Called after the block has been placed
+    fn on_place(&self, world: &mut ChunkServer, pos: BlockPos, state: BlockState) {
+        let _ = (world, pos, state);
+    }
+
+    /// Called after the block has been broken, with the state it had
+    fn on_break(&self, world: &mut ChunkServer, pos: BlockPos, state: BlockState) {
+        let _ = (world, pos, state);
+    }
+
+    /// Called after the block next to it (at `neighbor`) has been placed, broken or changed its
+    /// state
+    fn on_neighbor_changed(&self, world: &mut ChunkServer, pos: BlockPos, neighbor: BlockPos) {
+        let _ = (world, pos, neighbor);
+    }
+
+    /// Called when the player uses the block, returns whether something happened. If
+    /// not, the player places a block instead.
+    fn on_use(&self, world: &mut ChunkServer, pos: BlockPos, state: BlockState) -> bool {
+        let _ = (world, pos, state);
+        false
+    }
+}
+
+/// Cycles through the values of a state property when used, e.g. opening and closing a door
+pub struct CycleState {
+    pub property: String,
+}
+
+impl CycleState {
+    /// The state with the next value of the property, None if the block doesn't have it
+    fn next_state(&self, block: &BlockType, state: BlockState) -> Option<BlockState> {
+        let property = block.states.property(&self.property)?;
+        let next = &property.values[(property.index(state) + 1) % property.values.len()];
+        Some(block.states.set(state, &self.property, next))
+    }
+}
+
+impl BlockBehavior for CycleState {
+    fn on_use(&self, world: &mut ChunkServer, pos: BlockPos, state: BlockState) -> bool {
+        let blocks = world.block_manager.clone();
+        let block = match world.get_block(pos) {
+            Some(x) => x,
+            None => return false,
+        };
+        let state = match self.next_state(&blocks[block], state) {
+            Some(x) => x,
+            None => return false,
+        };
+        if world.set_block_state(pos, block, state).is_none() {
+            return false;
+        }
+        world.notify_neighbors(pos);
+        true
+    }
+}
+
+/// Has to stand on one of the listed blocks and breaks when it's gone, e.g. plants on soil
+pub struct NeedsSupport {
+    pub support: Vec<String>,
+}
+
+impl NeedsSupport {
+    fn is_supported(&self, world: &mut ChunkServer, pos: BlockPos) -> bool {
+        let blocks = world.block_manager.clone();
+        world
+            .get_block(pos + BlockPos::new(0, -1, 0))
+            .is_some_and(|x| self.support.contains(&blocks[x].name))
+    }
+}
+
+impl BlockBehavior for NeedsSupport {
+    fn can_place_at(&self, world: &mut ChunkServer, pos: BlockPos, _: BlockState) -> bool {
+        self.is_supported(world, pos)
+    }
+
+    fn on_neighbor_changed(&self, world: &mut ChunkServer, pos: BlockPos, _: BlockPos) {
+        if !self.is_supported(world, pos) {
+            world.break_block(pos);
+        }
+    }
+}
+
+/// Registers the behaviors of the built-in blocks, only for the blocks which have been loaded
+pub fn register_defaults(blocks: &mut BlockManager) {
+    for door in ["door", "trapdoor", "lever"] {
+        if blocks.get(door).is_none() {
+            continue;
+        }
+        blocks.register_behavior(
+            door,
+            Arc::new(CycleState {
+                property: String::from("open"),
+            }),
+        );
+    }
+    for plant in ["flower", "tall_grass", "sapling"] {
+        if blocks.get(plant).is_none() {
+            continue;
+        }
+        blocks.register_behavior(
+            plant,
+            Arc::new(NeedsSupport {
+                support: vec![String::from("grass"), String::from("dirt")],
+            }),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blocks() -> BlockManager {
+        BlockManager::from_files(&[String::from("blocks.json")]).unwrap()
+    }
+
+    #[test]
+    fn cycle_state_wraps_around() {
+        let blocks = blocks();
+        let log = &blocks[blocks.get("log").unwrap()];
+        let cycle = CycleState {
+            property: String::from("axis"),
+        };
+        let mut state = log.states.set(0, "axis", "y");
+        let mut values = Vec::new();
+        for _ in 0..4 {
+            state = cycle.next_state(log, state).unwrap();
+            values.push(log.states.get(state, "axis").unwrap());
+        }
+        assert_eq!(values, ["x", "z", "y", "x"]);
+        // blocks without the property can't be used
+        let stone = &blocks[blocks.get("stone").unwrap()];
+        assert_eq!(cycle.next_state(stone, 0), None);
+    }
+
+    #[test]
+    fn behaviors_are_dispatched_by_name() {
+        let mut blocks = blocks();
+        register_defaults(&mut blocks);
+        // none of the built-in behaviors belong to the blocks in blocks.json
+        assert!(blocks.behaviors.is_empty());
+        let cycle = Arc::new(CycleState {
+            property: String::from("axis"),
+        });
+        blocks.register_behavior("log", cycle.clone());
+        let log = blocks.get("log").unwrap();
+        let behavior = blocks.behavior(log).unwrap();
+        assert!(std::ptr::eq(
+            Arc::as_ptr(&behavior) as *const u8,
+            Arc::as_ptr(&cycle) as *const u8
+        ));
+        assert!(blocks.behavior(blocks.get("stone").unwrap()).is_none());
+    }
+}
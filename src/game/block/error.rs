@@ -0,0 +1,106 @@
+use std::fmt;
+
+/// Error produced while loading the blocks file
+#[derive(Debug)]
+pub struct BlockError {
+    pub filename: String,
+    /// Index and name (if it could be read) of the block in which the error occured
+    pub block: Option<(usize, Option<String>)>,
+    /// Path to the offending value in the json file, e.g. `[7].textures.top.left`
+    pub path: String,
+    pub kind: BlockErrorKind,
+}
+
+#[derive(Debug)]
+pub enum BlockErrorKind {
+    Io(std::io::Error),
+    Json(json::Error),
+    /// The top level value has to be an array of blocks
+    NotAnArray,
+    /// A required field is missing, contains the expected type
+    Missing(&'static str),
+    /// A field has the wrong type, contains the expected type
+    WrongType(&'static str),
+    /// The name has already been used by the block at the given index
+    DuplicateName(usize),
+    UnknownModel(String),
+    /// A state property has a value which isn't allowed, or a variant isn't one of the values
+    UnknownStateValue(String),
+    /// The state properties don't fit into a `BlockState`
+    TooManyStates,
+    /// A texture file can't be loaded, contains the file name
+    Texture(String, Box<image::ImageError>),
+    /// A texture file isn't in the atlas, contains the file name
+    UnknownTexture(String),
+    /// A texture rectangle doesn't fit into the texture
+    TextureOutOfBounds,
+    /// The height of an animated texture isn't a multiple of the number of frames
+    AnimationFrames,
+}
+
+impl BlockError {
+    pub fn new(kind: BlockErrorKind) -> Self {
+        Self {
+            filename: String::new(),
+            block: None,
+            path: String::new(),
+            kind,
+        }
+    }
+
+    /// Sets the file in which the error occured
+    pub fn in_file(mut self, filename: &str) -> Self {
+        self.filename = String::from(filename);
+        self
+    }
+}
+
+impl fmt::Display for BlockErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockErrorKind::Io(e) => write!(f, "cannot read file: {}", e),
+            BlockErrorKind::Json(e) => write!(f, "invalid json: {}", e),
+            BlockErrorKind::NotAnArray => write!(f, "expected an array of blocks"),
+            BlockErrorKind::Missing(t) => write!(f, "missing field, expected {}", t),
+            BlockErrorKind::WrongType(t) => write!(f, "expected {}", t),
+            BlockErrorKind::DuplicateName(first) => {
+                write!(f, "name already used by block {}", first)
+            }
+            BlockErrorKind::UnknownModel(model) => write!(f, "unknown model type \"{}\"", model),
+            BlockErrorKind::UnknownStateValue(value) => {
+                write!(f, "unknown state value \"{}\"", value)
+            }
+            BlockErrorKind::TooManyStates => write!(
+                f,
+                "the states need more than {} bits",
+                super::StateLayout::BITS
+            ),
+            BlockErrorKind::Texture(name, e) => write!(f, "cannot load texture {}: {}", name, e),
+            BlockErrorKind::UnknownTexture(name) => write!(f, "texture {} isn't loaded", name),
+            BlockErrorKind::TextureOutOfBounds => {
+                write!(f, "the rectangle doesn't fit into the texture")
+            }
+            BlockErrorKind::AnimationFrames => write!(
+                f,
+                "the height of the texture has to be a multiple of the number of frames"
+            ),
+        }
+    }
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.filename)?;
+        match &self.block {
+            Some((index, Some(name))) => write!(f, "block {} (\"{}\"): ", index, name)?,
+            Some((index, None)) => write!(f, "block {}: ", index)?,
+            None => (),
+        }
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl std::error::Error for BlockError {}
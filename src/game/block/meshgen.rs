@@ -1,106 +1,332 @@
 use super::*;
+use rand::Rng;
 use util::TexRect;
 
-pub fn append_cube(data: &mut MeshData, pos: Vec3, sides: BlockSides<bool>, texture: TexRect) {
-    let mut size: i32 = data.vertices.len() as _;
-    if sides.back {
-        data.vertices.push((pos.x + 0.0, pos.y + 0.0, pos.z + 0.0));
-        data.vertices.push((pos.x + 1.0, pos.y + 0.0, pos.z + 0.0));
-        data.vertices.push((pos.x + 0.0, pos.y + 1.0, pos.z + 0.0));
-        data.vertices.push((pos.x + 1.0, pos.y + 1.0, pos.z + 0.0));
-        data.indices.push((0 + size, 1 + size, 2 + size));
-        data.indices.push((1 + size, 2 + size, 3 + size));
-        data.texcoords.push((texture.left, texture.bottom));
-        data.texcoords.push((texture.right, texture.bottom));
-        data.texcoords.push((texture.left, texture.top));
-        data.texcoords.push((texture.right, texture.top));
-        for _ in 0..4 {
-            data.normals.push((0.0, 0.0, -1.0));
-        }
-        size += 4;
+/// A box of a `cuboids` model, in block-local coordinates
+#[derive(Clone, Copy, Debug)]
+pub struct Cuboid {
+    pub min: Vec3,
+    pub max: Vec3,
+    /// Faces without a texture aren't drawn
+    pub textures: BlockSides<Option<TexRect>>,
+    /// Stretch the whole texture over every face instead of cutting out the part covered by the
+    /// face, like on a full block
+    pub stretch: bool,
+    /// Rotation of the box in block-local coordinates
+    pub rotation: Option<Mat4>,
+}
+
+impl Cuboid {
+    /// A full block with the same texture on every side
+    pub fn cube(texture: TexRect) -> Self {
+        Self::sided(texture, texture, texture)
     }
-    if sides.left {
-        data.vertices.push((pos.x + 0.0, pos.y + 0.0, pos.z + 0.0));
-        data.vertices.push((pos.x + 0.0, pos.y + 1.0, pos.z + 0.0));
-        data.vertices.push((pos.x + 0.0, pos.y + 0.0, pos.z + 1.0));
-        data.vertices.push((pos.x + 0.0, pos.y + 1.0, pos.z + 1.0));
-        data.indices.push((0 + size, 1 + size, 2 + size));
-        data.indices.push((1 + size, 2 + size, 3 + size));
-        data.texcoords.push((texture.right, texture.bottom));
-        data.texcoords.push((texture.right, texture.top));
-        data.texcoords.push((texture.left, texture.bottom));
-        data.texcoords.push((texture.left, texture.top));
-        for _ in 0..4 {
-            data.normals.push((-1.0, 0.0, 0.0));
+
+    /// A full block with a separate texture for the top and the bottom
+    pub fn sided(side: TexRect, top: TexRect, bottom: TexRect) -> Self {
+        Cuboid {
+            min: Vec3::ZERO,
+            max: Vec3::ONE,
+            textures: BlockSides {
+                top: Some(top),
+                bottom: Some(bottom),
+                left: Some(side),
+                right: Some(side),
+                front: Some(side),
+                back: Some(side),
+            },
+            stretch: true,
+            rotation: None,
         }
-        size += 4;
     }
-    if sides.front {
-        data.vertices.push((pos.x + 0.0, pos.y + 0.0, pos.z + 1.0));
-        data.vertices.push((pos.x + 1.0, pos.y + 0.0, pos.z + 1.0));
-        data.vertices.push((pos.x + 0.0, pos.y + 1.0, pos.z + 1.0));
-        // Debug message
-        data.vertices.push((pos.x + 1.0, pos.y + 1.0, pos.z + 1.0));
-        data.indices.push((0 + size, 1 + size, 2 + size));
-        data.indices.push((1 + size, 2 + size, 3 + size));
-        data.texcoords.push((texture.right, texture.bottom));
-        data.texcoords.push((texture.left, texture.bottom));
-        data.texcoords.push((texture.right, texture.top));
-        data.texcoords.push((texture.left, texture.top));
-        for _ in 0..4 {
-            data.normals.push((0.0, 0.0, 1.0));
-        }
-        size += 4;
+}
+
+/// Linear interpolation between two texture coordinates
+#[inline]
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a * (1.0 - t) + b * t
+}
+
+/// The corners of a side of a box, going from `a` to `b`
+pub fn face_corners(side: BlockSide, a: Vec3, b: Vec3) -> [Vec3; 4] {
+    match side {
+        BlockSide::Back => [
+            Vec3::new(a.x, a.y, a.z),
+            Vec3::new(b.x, a.y, a.z),
+            Vec3::new(a.x, b.y, a.z),
+            Vec3::new(b.x, b.y, a.z),
+        ],
+        BlockSide::Left => [
+            Vec3::new(a.x, a.y, a.z),
+            Vec3::new(a.x, b.y, a.z),
+            Vec3::new(a.x, a.y, b.z),
+            Vec3::new(a.x, b.y, b.z),
+        ],
+        BlockSide::Front => [
+            Vec3::new(a.x, a.y, b.z),
+            Vec3::new(b.x, a.y, b.z),
+            Vec3::new(a.x, b.y, b.z),
+            Vec3::new(b.x, b.y, b.z),
+        ],
+        BlockSide::Right => [
+            Vec3::new(b.x, a.y, a.z),
+            Vec3::new(b.x, b.y, a.z),
+            Vec3::new(b.x, a.y, b.z),
+            Vec3::new(b.x, b.y, b.z),
+        ],
+        BlockSide::Top => [
+            Vec3::new(a.x, b.y, a.z),
+            Vec3::new(b.x, b.y, a.z),
+            Vec3::new(a.x, b.y, b.z),
+            Vec3::new(b.x, b.y, b.z),
+        ],
+        BlockSide::Bottom => [
+            Vec3::new(a.x, a.y, a.z),
+            Vec3::new(b.x, a.y, a.z),
+            Vec3::new(a.x, a.y, b.z),
+            Vec3::new(b.x, a.y, b.z),
+        ],
     }
-    if sides.right {
-        data.vertices.push((pos.x + 1.0, pos.y + 0.0, pos.z + 0.0));
-        data.vertices.push((pos.x + 1.0, pos.y + 1.0, pos.z + 0.0));
-        data.vertices.push((pos.x + 1.0, pos.y + 0.0, pos.z + 1.0));
-        data.vertices.push((pos.x + 1.0, pos.y + 1.0, pos.z + 1.0));
-        data.indices.push((0 + size, 1 + size, 2 + size));
-        data.indices.push((1 + size, 2 + size, 3 + size));
-        data.texcoords.push((texture.left, texture.bottom));
-        data.texcoords.push((texture.left, texture.top));
-        data.texcoords.push((texture.right, texture.bottom));
-        data.texcoords.push((texture.right, texture.top));
-        for _ in 0..4 {
-            data.normals.push((1.0, 0.0, 0.0));
-        }
-        size += 4;
+}
+
+/// How a side of a box is textured: the texture coordinates of the corners are
+/// `(lerp(u.0, u.1, t.0), lerp(v.0, v.1, t.1))`
+///
+/// `t` is the position of the corner on the texture, `texture` is returned as `(u, v)`
+fn face_uv(side: BlockSide, texture: TexRect, t: Vec3) -> ((f32, f32), (f32, f32), (f32, f32)) {
+    match side {
+        BlockSide::Back => (
+            (texture.left, texture.right),
+            (texture.bottom, texture.top),
+            (t.x, t.y),
+        ),
+        BlockSide::Left => (
+            (texture.right, texture.left),
+            (texture.bottom, texture.top),
+            (t.z, t.y),
+        ),
+        BlockSide::Front => (
+            (texture.right, texture.left),
+            (texture.bottom, texture.top),
+            (t.x, t.y),
+        ),
+        BlockSide::Right => (
+            (texture.left, texture.right),
+            (texture.bottom, texture.top),
+            (t.z, t.y),
+        ),
+        BlockSide::Top | BlockSide::Bottom => (
+            (texture.left, texture.right),
+            (texture.top, texture.bottom),
+            (t.z, t.x),
+        ),
     }
-    if sides.top {
-        data.vertices.push((pos.x + 0.0, pos.y + 1.0, pos.z + 0.0));
-        data.vertices.push((pos.x + 1.0, pos.y + 1.0, pos.z + 0.0));
-        data.vertices.push((pos.x + 0.0, pos.y + 1.0, pos.z + 1.0));
-        data.vertices.push((pos.x + 1.0, pos.y + 1.0, pos.z + 1.0));
-        data.indices.push((0 + size, 1 + size, 2 + size));
+}
+
+/// Appends a box, faces lying on the side of the block are only drawn if they are visible
+pub fn append_cuboid(data: &mut MeshData, pos: Vec3, sides: &BlockSides<bool>, cuboid: &Cuboid) {
+    let (a, b) = (cuboid.min, cuboid.max);
+    // faces in the same order as they have always been generated
+    let faces = [
+        (BlockSide::Back, a.z == 0.0),
+        (BlockSide::Left, a.x == 0.0),
+        (BlockSide::Front, b.z == 1.0),
+        (BlockSide::Right, b.x == 1.0),
+        (BlockSide::Top, b.y == 1.0),
+        (BlockSide::Bottom, a.y == 0.0),
+    ];
+    for (side, on_boundary) in faces {
+        let texture = match cuboid.textures.get(side) {
+            Some(x) => *x,
+            None => continue,
+        };
+        // rotated boxes don't line up with the neighbors, so they're always drawn
+        if on_boundary && cuboid.rotation.is_none() && !sides.get(side) {
+            continue;
+        }
+        let size: i32 = data.vertices.len() as _;
+        for c in face_corners(side, a, b) {
+            // position of the corner on the texture, either relative to the block or to the box
+            let t = if cuboid.stretch {
+                (c - a) / (b - a).max(Vec3::splat(f32::EPSILON))
+            } else {
+                c
+            };
+            let (u, v, t) = face_uv(side, texture, t);
+            data.texcoords
+                .push((lerp(u.0, u.1, t.0), lerp(v.0, v.1, t.1)));
+            let (c, normal) = match cuboid.rotation {
+                Some(mat) => (
+                    mat.transform_point3(c),
+                    mat.transform_vector3(side.to_pos().into()),
+                ),
+                None => (c, side.to_pos().into()),
+            };
+            let c = c + pos;
+            data.vertices.push((c.x, c.y, c.z));
+            data.normals.push((normal.x, normal.y, normal.z));
+        }
+        data.indices.push((size, 1 + size, 2 + size));
         data.indices.push((1 + size, 2 + size, 3 + size));
-        data.texcoords.push((texture.left, texture.top));
-        data.texcoords.push((texture.left, texture.bottom));
-        data.texcoords.push((texture.right, texture.top));
-        data.texcoords.push((texture.right, texture.bottom));
-        for _ in 0..4 {
-            data.normals.push((0.0, 1.0, 0.0));
+    }
+}
+
+/// The two triangles of a quad starting at vertex `size`, split along the diagonal which makes
+/// the ambient occlusion of the corners (in `face_corners` order) look the same in every
+/// direction
+pub fn quad_indices(size: i32, ao: [f32; 4]) -> [(i32, i32, i32); 2] {
+    if ao[0] + ao[3] < ao[1] + ao[2] {
+        [(size, 1 + size, 3 + size), (size, 2 + size, 3 + size)]
+    } else {
+        [(size, 1 + size, 2 + size), (1 + size, 2 + size, 3 + size)]
+    }
+}
+
+/// A face of a full block which can be merged with the same faces next to it
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct GreedyFace {
+    pub texture: TexRect,
+    pub color: Vec3,
+    /// Ambient occlusion of the corners, in `face_corners` order
+    pub ao: [f32; 4],
+}
+
+impl GreedyFace {
+    /// Faces which are darker in some corners can't be merged, the shading would be stretched
+    /// over the whole quad
+    fn is_mergeable(&self) -> bool {
+        self.ao.iter().all(|x| *x == self.ao[0])
+    }
+}
+
+/// The visible faces of the full blocks in a chunk, which get merged into larger quads by
+/// `append_greedy`
+pub struct GreedyFaces {
+    faces: BlockSides<Vec<Option<GreedyFace>>>,
+}
+
+impl GreedyFaces {
+    pub fn new() -> Self {
+        Self {
+            faces: BlockSides::from_fn(|_| vec![None; 16 * 16 * 16]),
         }
-        size += 4;
     }
-    if sides.bottom {
-        data.vertices.push((pos.x + 0.0, pos.y + 0.0, pos.z + 0.0));
-        data.vertices.push((pos.x + 1.0, pos.y + 0.0, pos.z + 0.0));
-        data.vertices.push((pos.x + 0.0, pos.y + 0.0, pos.z + 1.0));
-        data.vertices.push((pos.x + 1.0, pos.y + 0.0, pos.z + 1.0));
-        data.indices.push((0 + size, 1 + size, 2 + size));
-        data.indices.push((1 + size, 2 + size, 3 + size));
-        data.texcoords.push((texture.left, texture.top));
-        data.texcoords.push((texture.left, texture.bottom));
-        data.texcoords.push((texture.right, texture.top));
-        data.texcoords.push((texture.right, texture.bottom));
-        for _ in 0..4 {
-            data.normals.push((0.0, -1.0, 0.0));
+
+    /// Adds a face of the block at a position inside the chunk
+    pub fn add(&mut self, side: BlockSide, x: usize, y: usize, z: usize, face: GreedyFace) {
+        let faces = match side {
+            BlockSide::Top => &mut self.faces.top,
+            BlockSide::Bottom => &mut self.faces.bottom,
+            BlockSide::Left => &mut self.faces.left,
+            BlockSide::Right => &mut self.faces.right,
+            BlockSide::Front => &mut self.faces.front,
+            BlockSide::Back => &mut self.faces.back,
+        };
+        faces[(x * 16 + y) * 16 + z] = Some(face);
+    }
+}
+
+/// Merges the faces of full blocks into as few quads as possible and appends them, `origin` is
+/// the position of the chunk
+///
+/// The texture coordinates are in units of 16 tiles and the texture repeats on every block, see
+/// `MeshData::tiles`
+pub fn append_greedy(data: &mut MeshData, origin: Vec3, faces: &GreedyFaces) {
+    data.colors.resize(data.vertices.len(), (1.0, 1.0, 1.0));
+    data.tiles.resize(data.vertices.len(), (0.0, 0.0, 0.0, 0.0));
+    data.ao.resize(data.vertices.len(), 1.0);
+    for side in BlockSide::ALL {
+        let faces = faces.faces.get(side);
+        // the axis the face points along, and the two axes of the layer
+        let (n, u, v) = match side {
+            BlockSide::Left | BlockSide::Right => (0, 1, 2),
+            BlockSide::Top | BlockSide::Bottom => (1, 0, 2),
+            BlockSide::Front | BlockSide::Back => (2, 0, 1),
+        };
+        let index = |layer: usize, i: usize, j: usize| {
+            let mut p = [0; 3];
+            p[n] = layer;
+            p[u] = i;
+            p[v] = j;
+            (p[0] * 16 + p[1]) * 16 + p[2]
+        };
+        for layer in 0..16 {
+            let mut mask: Vec<Option<GreedyFace>> = (0..256)
+                .map(|x| faces[index(layer, x / 16, x % 16)])
+                .collect();
+            for i in 0..16 {
+                let mut j = 0;
+                while j < 16 {
+                    let face = match mask[i * 16 + j] {
+                        Some(x) => x,
+                        None => {
+                            j += 1;
+                            continue;
+                        }
+                    };
+                    // grow along v, then along u as long as the whole row matches
+                    let mergeable = face.is_mergeable();
+                    let mut h = 1;
+                    while mergeable && j + h < 16 && mask[i * 16 + j + h] == Some(face) {
+                        h += 1;
+                    }
+                    let mut w = 1;
+                    while mergeable
+                        && i + w < 16
+                        && (j..j + h).all(|y| mask[(i + w) * 16 + y] == Some(face))
+                    {
+                        w += 1;
+                    }
+                    for x in i..i + w {
+                        for y in j..j + h {
+                            mask[x * 16 + y] = None;
+                        }
+                    }
+                    let mut a = Vec3::ZERO;
+                    a[n] = layer as f32;
+                    a[u] = i as f32;
+                    a[v] = j as f32;
+                    let mut b = a + Vec3::ONE;
+                    b[u] = a[u] + w as f32;
+                    b[v] = a[v] + h as f32;
+                    append_greedy_quad(data, origin, side, a, b, face);
+                    j += h;
+                }
+            }
         }
     }
 }
 
+/// Appends one merged face of the box going from `a` to `b`, the texture is repeated once per
+/// unit and `b - a` may be at most 16
+pub fn append_greedy_quad(
+    data: &mut MeshData,
+    origin: Vec3,
+    side: BlockSide,
+    a: Vec3,
+    b: Vec3,
+    face: GreedyFace,
+) {
+    let size: i32 = data.vertices.len() as _;
+    let normal: Vec3 = side.to_pos().into();
+    for c in face_corners(side, a, b) {
+        // the texture of a full block goes from 0 to 1, so it's repeated on every block
+        let (u, v, t) = face_uv(side, face.texture, c - a);
+        data.texcoords.push((t.0 / 16.0, t.1 / 16.0));
+        data.tiles.push((u.0, v.0, u.1, v.1));
+        let c = c + origin;
+        data.vertices.push((c.x, c.y, c.z));
+        data.normals.push((normal.x, normal.y, normal.z));
+        data.colors.push((face.color.x, face.color.y, face.color.z));
+    }
+    data.ao.extend_from_slice(&face.ao);
+    data.indices.extend_from_slice(&quad_indices(size, face.ao));
+}
+
+pub fn append_cube(data: &mut MeshData, pos: Vec3, sides: BlockSides<bool>, texture: TexRect) {
+    append_cuboid(data, pos, &sides, &Cuboid::cube(texture));
+}
+
 pub fn append_cube_sided(
     data: &mut MeshData,
     pos: Vec3,
@@ -109,103 +335,7 @@ pub fn append_cube_sided(
     top: TexRect,
     bottom: TexRect,
 ) {
-    let mut size: i32 = data.vertices.len() as _;
-    if sides.back {
-        data.vertices.push((pos.x + 0.0, pos.y + 0.0, pos.z + 0.0));
-        data.vertices.push((pos.x + 1.0, pos.y + 0.0, pos.z + 0.0));
-        data.vertices.push((pos.x + 0.0, pos.y + 1.0, pos.z + 0.0));
-        data.vertices.push((pos.x + 1.0, pos.y + 1.0, pos.z + 0.0));
-        data.indices.push((0 + size, 1 + size, 2 + size));
-        data.indices.push((1 + size, 2 + size, 3 + size));
-        data.texcoords.push((side.left, side.bottom));
-        data.texcoords.push((side.right, side.bottom));
-        data.texcoords.push((side.left, side.top));
-        data.texcoords.push((side.right, side.top));
-        for _ in 0..4 {
-            data.normals.push((0.0, 0.0, -1.0));
-        }
-        size += 4;
-    }
-    if sides.left {
-        data.vertices.push((pos.x + 0.0, pos.y + 0.0, pos.z + 0.0));
-        data.vertices.push((pos.x + 0.0, pos.y + 1.0, pos.z + 0.0));
-        data.vertices.push((pos.x + 0.0, pos.y + 0.0, pos.z + 1.0));
-        data.vertices.push((pos.x + 0.0, pos.y + 1.0, pos.z + 1.0));
-        data.indices.push((0 + size, 1 + size, 2 + size));
-        data.indices.push((1 + size, 2 + size, 3 + size));
-        data.texcoords.push((side.right, side.bottom));
-        data.texcoords.push((side.right, side.top));
-        data.texcoords.push((side.left, side.bottom));
-        data.texcoords.push((side.left, side.top));
-        for _ in 0..4 {
-            data.normals.push((-1.0, 0.0, 0.0));
-        }
-        size += 4;
-    }
-    if sides.front {
-        data.vertices.push((pos.x + 0.0, pos.y + 0.0, pos.z + 1.0));
-        data.vertices.push((pos.x + 1.0, pos.y + 0.0, pos.z + 1.0));
-        data.vertices.push((pos.x + 0.0, pos.y + 1.0, pos.z + 1.0));
-        // Debug message
-        data.vertices.push((pos.x + 1.0, pos.y + 1.0, pos.z + 1.0));
-        data.indices.push((0 + size, 1 + size, 2 + size));
-        data.indices.push((1 + size, 2 + size, 3 + size));
-        data.texcoords.push((side.right, side.bottom));
-        data.texcoords.push((side.left, side.bottom));
-        data.texcoords.push((side.right, side.top));
-        data.texcoords.push((side.left, side.top));
-        for _ in 0..4 {
-            data.normals.push((0.0, 0.0, 1.0));
-        }
-        size += 4;
-    }
-    if sides.right {
-        data.vertices.push((pos.x + 1.0, pos.y + 0.0, pos.z + 0.0));
-        data.vertices.push((pos.x + 1.0, pos.y + 1.0, pos.z + 0.0));
-        data.vertices.push((pos.x + 1.0, pos.y + 0.0, pos.z + 1.0));
-        data.vertices.push((pos.x + 1.0, pos.y + 1.0, pos.z + 1.0));
-        data.indices.push((0 + size, 1 + size, 2 + size));
-        data.indices.push((1 + size, 2 + size, 3 + size));
-        data.texcoords.push((side.left, side.bottom));
-        data.texcoords.push((side.left, side.top));
-        data.texcoords.push((side.right, side.bottom));
-        data.texcoords.push((side.right, side.top));
-        for _ in 0..4 {
-            data.normals.push((1.0, 0.0, 0.0));
-        }
-        size += 4;
-    }
-    if sides.top {
-        data.vertices.push((pos.x + 0.0, pos.y + 1.0, pos.z + 0.0));
-        data.vertices.push((pos.x + 1.0, pos.y + 1.0, pos.z + 0.0));
-        data.vertices.push((pos.x + 0.0, pos.y + 1.0, pos.z + 1.0));
-        data.vertices.push((pos.x + 1.0, pos.y + 1.0, pos.z + 1.0));
-        data.indices.push((0 + size, 1 + size, 2 + size));
-        data.indices.push((1 + size, 2 + size, 3 + size));
-        data.texcoords.push((top.left, top.top));
-        data.texcoords.push((top.left, top.bottom));
-        data.texcoords.push((top.right, top.top));
-        data.texcoords.push((top.right, top.bottom));
-        for _ in 0..4 {
-            data.normals.push((0.0, 1.0, 0.0));
-        }
-        size += 4;
-    }
-    if sides.bottom {
-        data.vertices.push((pos.x + 0.0, pos.y + 0.0, pos.z + 0.0));
-        data.vertices.push((pos.x + 1.0, pos.y + 0.0, pos.z + 0.0));
-        data.vertices.push((pos.x + 0.0, pos.y + 0.0, pos.z + 1.0));
-        data.vertices.push((pos.x + 1.0, pos.y + 0.0, pos.z + 1.0));
-        data.indices.push((0 + size, 1 + size, 2 + size));
-        data.indices.push((1 + size, 2 + size, 3 + size));
-        data.texcoords.push((bottom.left, bottom.top));
-        data.texcoords.push((bottom.left, bottom.bottom));
-        data.texcoords.push((bottom.right, bottom.top));
-        data.texcoords.push((bottom.right, bottom.bottom));
-        for _ in 0..4 {
-            data.normals.push((0.0, -1.0, 0.0));
-        }
-    }
+    append_cuboid(data, pos, &sides, &Cuboid::sided(side, top, bottom));
 }
 
 pub fn append_slab(
@@ -216,137 +346,75 @@ pub fn append_slab(
     top: TexRect,
     bottom: TexRect,
 ) {
-    let mut size: i32 = data.vertices.len() as _;
-    if sides.back {
-        data.vertices.push((pos.x + 0.0, pos.y + 0.0, pos.z + 0.0));
-        data.vertices.push((pos.x + 1.0, pos.y + 0.0, pos.z + 0.0));
-        data.vertices.push((pos.x + 0.0, pos.y + 0.5, pos.z + 0.0));
-        data.vertices.push((pos.x + 1.0, pos.y + 0.5, pos.z + 0.0));
-        data.indices.push((0 + size, 1 + size, 2 + size));
-        data.indices.push((1 + size, 2 + size, 3 + size));
-        data.texcoords.push((side.left, side.bottom));
-        data.texcoords.push((side.right, side.bottom));
-        data.texcoords.push((side.left, side.top));
-        data.texcoords.push((side.right, side.top));
-        for _ in 0..4 {
-            data.normals.push((0.0, 0.0, -1.0));
-        }
-        size += 4;
-    }
-    if sides.left {
-        data.vertices.push((pos.x + 0.0, pos.y + 0.0, pos.z + 0.0));
-        data.vertices.push((pos.x + 0.0, pos.y + 0.5, pos.z + 0.0));
-        data.vertices.push((pos.x + 0.0, pos.y + 0.0, pos.z + 1.0));
-        data.vertices.push((pos.x + 0.0, pos.y + 0.5, pos.z + 1.0));
-        data.indices.push((0 + size, 1 + size, 2 + size));
-        data.indices.push((1 + size, 2 + size, 3 + size));
-        data.texcoords.push((side.right, side.bottom));
-        data.texcoords.push((side.right, side.top));
-        data.texcoords.push((side.left, side.bottom));
-        data.texcoords.push((side.left, side.top));
-        for _ in 0..4 {
-            data.normals.push((-1.0, 0.0, 0.0));
-        }
-        size += 4;
-    }
-    if sides.front {
-        data.vertices.push((pos.x + 0.0, pos.y + 0.0, pos.z + 1.0));
-        data.vertices.push((pos.x + 1.0, pos.y + 0.0, pos.z + 1.0));
-        data.vertices.push((pos.x + 0.0, pos.y + 0.5, pos.z + 1.0));
-        // Debug message
-        data.vertices.push((pos.x + 1.0, pos.y + 0.5, pos.z + 1.0));
-        data.indices.push((0 + size, 1 + size, 2 + size));
-        data.indices.push((1 + size, 2 + size, 3 + size));
-        data.texcoords.push((side.right, side.bottom));
-        data.texcoords.push((side.left, side.bottom));
-        data.texcoords.push((side.right, side.top));
-        data.texcoords.push((side.left, side.top));
-        for _ in 0..4 {
-            data.normals.push((0.0, 0.0, 1.0));
-        }
-        size += 4;
-    }
-    if sides.right {
-        data.vertices.push((pos.x + 1.0, pos.y + 0.0, pos.z + 0.0));
-        data.vertices.push((pos.x + 1.0, pos.y + 0.5, pos.z + 0.0));
-        data.vertices.push((pos.x + 1.0, pos.y + 0.0, pos.z + 1.0));
-        data.vertices.push((pos.x + 1.0, pos.y + 0.5, pos.z + 1.0));
-        data.indices.push((0 + size, 1 + size, 2 + size));
-        data.indices.push((1 + size, 2 + size, 3 + size));
-        data.texcoords.push((side.left, side.bottom));
-        data.texcoords.push((side.left, side.top));
-        data.texcoords.push((side.right, side.bottom));
-        data.texcoords.push((side.right, side.top));
-        for _ in 0..4 {
-            data.normals.push((1.0, 0.0, 0.0));
+    let mut slab = Cuboid::sided(side, top, bottom);
+    slab.max.y = 0.5;
+    append_cuboid(data, pos, &sides, &slab);
+}
+
+/// Two crossed quads going diagonally through the block, for flowers and grass
+pub fn append_cross(data: &mut MeshData, pos: Vec3, texture: TexRect) {
+    let planes = [
+        (Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 1.0)),
+        (Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0)),
+    ];
+    for (a, b) in planes {
+        let size: i32 = data.vertices.len() as _;
+        for v in [a, b, a + Vec3::Y, b + Vec3::Y] {
+            let v = v + pos;
+            data.vertices.push((v.x, v.y, v.z));
         }
-        size += 4;
-    }
-    if sides.top {
-        data.vertices.push((pos.x + 0.0, pos.y + 0.5, pos.z + 0.0));
-        data.vertices.push((pos.x + 1.0, pos.y + 0.5, pos.z + 0.0));
-        data.vertices.push((pos.x + 0.0, pos.y + 0.5, pos.z + 1.0));
-        data.vertices.push((pos.x + 1.0, pos.y + 0.5, pos.z + 1.0));
-        data.indices.push((0 + size, 1 + size, 2 + size));
-        data.indices.push((1 + size, 2 + size, 3 + size));
-        data.texcoords.push((top.left, top.top));
-        data.texcoords.push((top.left, top.bottom));
-        data.texcoords.push((top.right, top.top));
-        data.texcoords.push((top.right, top.bottom));
+        data.texcoords.push((texture.left, texture.bottom));
+        data.texcoords.push((texture.right, texture.bottom));
+        data.texcoords.push((texture.left, texture.top));
+        data.texcoords.push((texture.right, texture.top));
+        let normal = (b - a).cross(Vec3::Y).normalize();
         for _ in 0..4 {
-            data.normals.push((0.0, 1.0, 0.0));
+            data.normals.push((normal.x, normal.y, normal.z));
         }
-        size += 4;
-    }
-    if sides.bottom {
-        data.vertices.push((pos.x + 0.0, pos.y + 0.0, pos.z + 0.0));
-        data.vertices.push((pos.x + 1.0, pos.y + 0.0, pos.z + 0.0));
-        data.vertices.push((pos.x + 0.0, pos.y + 0.0, pos.z + 1.0));
-        data.vertices.push((pos.x + 1.0, pos.y + 0.0, pos.z + 1.0));
-        data.indices.push((0 + size, 1 + size, 2 + size));
+        data.indices.push((size, 1 + size, 2 + size));
         data.indices.push((1 + size, 2 + size, 3 + size));
-        data.texcoords.push((bottom.left, bottom.top));
-        data.texcoords.push((bottom.left, bottom.bottom));
-        data.texcoords.push((bottom.right, bottom.top));
-        data.texcoords.push((bottom.right, bottom.bottom));
-        for _ in 0..4 {
-            data.normals.push((0.0, -1.0, 0.0));
-        }
     }
 }
 
-pub fn append_hedge(data: &mut MeshData, pos: Vec3, texture: TexRect, density: u32) {
+/// Random triangles around the block, for leaves
+///
+/// The triangles only depend on `detail`, so the block looks the same every time it's meshed.
+pub fn append_hedge(
+    data: &mut MeshData,
+    pos: Vec3,
+    texture: TexRect,
+    density: u32,
+    detail: MeshDetail,
+) {
+    // with a lower density, the triangles are the first ones of the full density
+    let density = (density as f32 * detail.density.clamp(0.0, 1.0)).round() as u32;
+    let mut rng = detail.rng();
     let mut size: i32 = data.vertices.len() as _;
     for _ in 0..density {
-        let p = pos
-            + Vec3::new(
-                rand::random::<f32>(),
-                rand::random::<f32>(),
-                rand::random::<f32>(),
-            );
+        let p = pos + Vec3::new(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>());
         data.vertices.push((
-            p.x + rand::random::<f32>() - 0.5,
-            p.y + rand::random::<f32>() - 0.5,
-            p.z + rand::random::<f32>() - 0.5,
+            p.x + rng.gen::<f32>() - 0.5,
+            p.y + rng.gen::<f32>() - 0.5,
+            p.z + rng.gen::<f32>() - 0.5,
         ));
         data.vertices.push((
-            p.x + rand::random::<f32>() - 0.5,
-            p.y + rand::random::<f32>() - 0.5,
-            p.z + rand::random::<f32>() - 0.5,
+            p.x + rng.gen::<f32>() - 0.5,
+            p.y + rng.gen::<f32>() - 0.5,
+            p.z + rng.gen::<f32>() - 0.5,
         ));
         data.vertices.push((
-            p.x + rand::random::<f32>() - 0.5,
-            p.y + rand::random::<f32>() - 0.5,
-            p.z + rand::random::<f32>() - 0.5,
+            p.x + rng.gen::<f32>() - 0.5,
+            p.y + rng.gen::<f32>() - 0.5,
+            p.z + rng.gen::<f32>() - 0.5,
         ));
-        data.indices.push((0 + size, 1 + size, 2 + size));
+        data.indices.push((size, 1 + size, 2 + size));
         data.texcoords.push((texture.left, texture.bottom));
         data.texcoords.push((texture.right, texture.bottom));
         data.texcoords.push((texture.left, texture.top));
         let normals = Vec3::new(
-            rand::random::<f32>() * 2.0 + 1.0,
-            rand::random::<f32>() * 2.0 + 1.0,
-            rand::random::<f32>() * 2.0 + 1.0,
+            rng.gen::<f32>() * 2.0 + 1.0,
+            rng.gen::<f32>() * 2.0 + 1.0,
+            rng.gen::<f32>() * 2.0 + 1.0,
         )
         .normalize_or_zero();
         for _ in 0..3 {
@@ -355,3 +423,65 @@ pub fn append_hedge(data: &mut MeshData, pos: Vec3, texture: TexRect, density: u
         size += 3;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn face(ao: [f32; 4]) -> GreedyFace {
+        GreedyFace {
+            texture: TexRect {
+                left: 0.0,
+                top: 0.0,
+                right: 0.25,
+                bottom: 0.25,
+            },
+            color: Vec3::ONE,
+            ao,
+        }
+    }
+
+    /// How many quads the faces get merged into
+    fn quads(faces: &GreedyFaces) -> usize {
+        let mut data = MeshData::new();
+        append_greedy(&mut data, Vec3::ZERO, faces);
+        assert_eq!(data.vertices.len(), data.indices.len() * 2);
+        data.indices.len() / 2
+    }
+
+    #[test]
+    fn flat_layer_is_one_quad() {
+        let mut faces = GreedyFaces::new();
+        for x in 0..16 {
+            for z in 0..16 {
+                faces.add(BlockSide::Top, x, 3, z, face([1.0; 4]));
+            }
+        }
+        let mut data = MeshData::new();
+        append_greedy(&mut data, Vec3::ZERO, &faces);
+        assert_eq!(data.indices.len(), 2);
+        let top = data.vertices.iter().map(|x| x.1).fold(f32::MIN, f32::max);
+        let width = data.vertices.iter().map(|x| x.0).fold(f32::MIN, f32::max);
+        assert_eq!((top, width), (4.0, 16.0));
+    }
+
+    #[test]
+    fn different_faces_stay_apart() {
+        let mut faces = GreedyFaces::new();
+        for x in 0..16 {
+            faces.add(BlockSide::Top, x, 0, 0, face([1.0; 4]));
+        }
+        // a darker face splits the row into three quads
+        faces.add(BlockSide::Top, 5, 0, 0, face([0.5; 4]));
+        assert_eq!(quads(&faces), 3);
+    }
+
+    #[test]
+    fn faces_with_mixed_ao_are_not_merged() {
+        let mut faces = GreedyFaces::new();
+        for x in 0..4 {
+            faces.add(BlockSide::Front, x, 0, 0, face([0.5, 1.0, 1.0, 1.0]));
+        }
+        assert_eq!(quads(&faces), 4);
+    }
+}
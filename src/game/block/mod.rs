@@ -1,48 +1,502 @@
 use super::*;
+use json::JsonValue;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::Read;
 use util::BlockCollider;
 
+mod atlas;
+pub mod behavior;
+mod error;
 mod meshgen;
+mod occlusion;
+mod state;
 
-use util::BlockSides;
+pub use atlas::Atlas;
+pub use behavior::BlockBehavior;
+pub use error::{BlockError, BlockErrorKind};
+pub use meshgen::{
+    append_greedy, append_greedy_quad, face_corners, quad_indices, GreedyFace, GreedyFaces,
+};
+pub use occlusion::FaceShape;
+pub use state::{BlockState, StateLayout};
+
+use util::{BlockSide, BlockSides};
 
 pub type BlockID = i32;
 
+/// Appends the mesh of a block at the given position, only drawing the sides marked as visible
+///
+/// The state is only used for choosing the variant, the block is rotated by `BlockType::mesh`
+pub type MeshFn =
+    Arc<dyn Fn(&mut MeshData, BlockPos, BlockSides<bool>, BlockState, MeshDetail) + Sync + Send>;
+
+/// What the mesh of a block depends on besides the block itself
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MeshDetail {
+    /// Seeds the random parts of models, like the leaves of hedges, so they look the same every
+    /// time they are meshed. `BlockType::mesh` mixes the position of the block into it.
+    pub seed: u64,
+    /// How much of the random detail is generated, from 0 to 1. Lower for chunks far away.
+    pub density: f32,
+}
+
+impl Default for MeshDetail {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            density: 1.0,
+        }
+    }
+}
+
+impl MeshDetail {
+    /// The detail of the block at a position, with a seed of its own
+    pub fn at(&self, pos: BlockPos) -> Self {
+        let mut seed = self.seed;
+        for x in [pos.x, pos.y, pos.z] {
+            seed = (seed ^ x as u32 as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+            seed ^= seed >> 32;
+        }
+        Self { seed, ..*self }
+    }
+
+    /// The level of detail of a chunk `distance` chunks away from the camera, 0 is the most
+    /// detailed one. The distances of the levels double.
+    pub fn level(distance: u32) -> u32 {
+        (distance / 2).max(1).ilog2().min(3)
+    }
+
+    /// The same detail with a density for the given level, halved with every level
+    pub fn with_level(self, level: u32) -> Self {
+        Self {
+            density: 0.5f32.powi(level as i32),
+            ..self
+        }
+    }
+
+    /// A random number generator seeded with `seed`
+    pub fn rng(&self) -> StdRng {
+        StdRng::seed_from_u64(self.seed)
+    }
+}
+
+/// Which render pass the block is drawn in
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RenderLayer {
+    /// Every pixel is fully opaque
+    Opaque,
+    /// Pixels are either fully opaque or fully transparent, like leaves
+    Cutout,
+    /// Partially transparent pixels, like glass or water
+    Translucent,
+}
+
+/// Where the color the texture of a block is multiplied with comes from
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TintSource {
+    Constant(Vec3),
+    /// Changes with the climate, see `world::biome`
+    Grass,
+    Foliage,
+}
+
+impl TintSource {
+    pub fn color(&self, pos: BlockPos) -> Vec3 {
+        match self {
+            TintSource::Constant(c) => *c,
+            TintSource::Grass => world::biome::grass_color(pos.x, pos.z),
+            TintSource::Foliage => world::biome::foliage_color(pos.x, pos.z),
+        }
+    }
+}
+
+/// Colors the mesh of a block, declared in blocks.json like `"tint": "grass"`
+#[derive(Clone, Copy, Debug)]
+pub struct Tint {
+    pub source: TintSource,
+    /// Which faces are tinted, decided by the direction of their normals
+    pub faces: BlockSides<bool>,
+}
+
 pub struct BlockType {
-    pub gen_mesh: Arc<dyn Fn(&mut MeshData, BlockPos, BlockSides<bool>) + Sync + Send>,
+    pub gen_mesh: MeshFn,
+    /// The textures of the sides if the block is a plain full cube, which can be merged with its
+    /// neighbors by greedy meshing
+    pub cube: Option<BlockSides<util::TexRect>>,
     pub collider: Vec<BlockCollider>,
-    pub solid: bool,
+    /// The boxes which the player can aim at
+    pub selection: Vec<BlockCollider>,
+    /// Whether the block is dark inside, it darkens the corners of its neighbors and covers grass
+    pub opaque: bool,
+    /// The shapes of the sides in every state, which decide which faces of the neighbors are
+    /// hidden
+    pub occlusion: Vec<BlockSides<FaceShape>>,
+    /// Whether the player collides with the block
+    pub collidable: bool,
+    /// Whether the block can be targeted by the player
+    pub selectable: bool,
+    /// Whether the block falls down when there is nothing to stand on, like sand
+    pub falls: bool,
+    pub render_layer: RenderLayer,
+    pub tint: Option<Tint>,
     pub name: String,
+    pub states: StateLayout,
 }
 
 pub struct BlockManager {
     name_index: HashMap<String, BlockID>,
     blocks: Vec<BlockType>,
+    /// The textures of all of the blocks
+    pub atlas: Atlas,
+    /// Custom logic, by block name
+    behaviors: HashMap<String, Arc<dyn BlockBehavior>>,
 }
 
 unsafe impl Sync for BlockType {}
 unsafe impl Sync for BlockManager {}
 
+impl BlockType {
+    /// Appends the mesh of the block in the given state
+    pub fn mesh(
+        &self,
+        data: &mut MeshData,
+        pos: BlockPos,
+        sides: BlockSides<bool>,
+        state: BlockState,
+        detail: MeshDetail,
+    ) {
+        let start = data.vertices.len();
+        let detail = detail.at(pos);
+        match self.states.transform(state) {
+            Some(mat) => self.mesh_transformed(data, pos, sides, state, detail, mat),
+            None => (self.gen_mesh)(data, pos, sides, state, detail),
+        }
+
+        // color the new vertices
+        data.colors.resize(start, (1.0, 1.0, 1.0));
+        let tint = match self.tint {
+            Some(x) => x,
+            None => return data.colors.resize(data.vertices.len(), (1.0, 1.0, 1.0)),
+        };
+        let color = tint.source.color(pos);
+        for n in &data.normals[start..] {
+            let side = BlockSide::from_vec(Vec3::new(n.0, n.1, n.2));
+            data.colors.push(if *tint.faces.get(side) {
+                (color.x, color.y, color.z)
+            } else {
+                (1.0, 1.0, 1.0)
+            });
+        }
+    }
+
+    /// Appends the mesh of the block turned by `mat`
+    fn mesh_transformed(
+        &self,
+        data: &mut MeshData,
+        pos: BlockPos,
+        sides: BlockSides<bool>,
+        state: BlockState,
+        detail: MeshDetail,
+        mat: Mat4,
+    ) {
+        // generate the model at the origin with the sides turned the same way as the model, then
+        // turn it and move it into place
+        let sides = BlockSides::from_fn(|x| {
+            *sides.get(BlockSide::from_vec(
+                mat.transform_vector3(x.to_pos().into()),
+            ))
+        });
+        let mut local = MeshData::new();
+        (self.gen_mesh)(&mut local, BlockPos::new(0, 0, 0), sides, state, detail);
+        let offset: Vec3 = pos.into();
+        for i in &mut local.vertices {
+            let v = mat.transform_point3(Vec3::new(i.0, i.1, i.2)) + offset;
+            *i = (v.x, v.y, v.z);
+        }
+        for i in &mut local.normals {
+            let v = mat.transform_vector3(Vec3::new(i.0, i.1, i.2));
+            *i = (v.x, v.y, v.z);
+        }
+        data.append(local);
+    }
+
+    /// Whether the block can be greedy meshed in the given state, it has to be a full cube which
+    /// isn't turned and whose color doesn't change with the position
+    pub fn is_greedy(&self, state: BlockState) -> bool {
+        self.cube.is_some()
+            && self.states.transform(state).is_none()
+            && self
+                .tint
+                .is_none_or(|x| matches!(x.source, TintSource::Constant(_)))
+    }
+
+    /// A side of the block for greedy meshing, only call if `is_greedy`
+    pub fn greedy_face(&self, side: BlockSide) -> GreedyFace {
+        let color = match self.tint {
+            Some(Tint {
+                source: TintSource::Constant(c),
+                faces,
+            }) if *faces.get(side) => c,
+            _ => Vec3::ONE,
+        };
+        GreedyFace {
+            texture: *self.cube.as_ref().unwrap().get(side),
+            color,
+            ao: [1.0; 4],
+        }
+    }
+
+    /// A side of the block as a quad of the coarse meshes of far away terrain, None if it isn't a
+    /// plain full cube
+    pub fn far_face(&self, side: BlockSide, pos: BlockPos) -> Option<GreedyFace> {
+        let color = match self.tint {
+            Some(tint) if *tint.faces.get(side) => tint.source.color(pos),
+            _ => Vec3::ONE,
+        };
+        Some(GreedyFace {
+            texture: *self.cube.as_ref()?.get(side),
+            color,
+            ao: [1.0; 4],
+        })
+    }
+
+    /// The shape of a side of the block in the given state
+    pub fn face_shape(&self, state: BlockState, side: BlockSide) -> FaceShape {
+        self.occlusion
+            .get(state as usize)
+            .map_or(FaceShape::default(), |x| *x.get(side))
+    }
+
+    /// Whether nothing can be seen through the block, because every side is completely covered
+    pub fn is_solid(&self, state: BlockState) -> bool {
+        BlockSide::ALL
+            .iter()
+            .all(|&x| self.face_shape(state, x).covers == u16::MAX)
+    }
+
+    /// Whether a side of the block is visible next to the `neighbor` block on that side
+    ///
+    /// Translucent blocks also hide the sides of the same block type
+    pub fn is_face_visible(
+        &self,
+        state: BlockState,
+        side: BlockSide,
+        neighbor: &BlockType,
+        neighbor_state: BlockState,
+    ) -> bool {
+        let shape = self.face_shape(state, side);
+        let other = neighbor.face_shape(neighbor_state, -side);
+        if self.render_layer == RenderLayer::Translucent && std::ptr::eq(self, neighbor) {
+            shape.touches & !other.fills != 0
+        } else {
+            shape.is_visible_next_to(&other)
+        }
+    }
+
+    /// Finds the shapes of the sides in every state from the mesh, only blocks drawn in the
+    /// opaque layer hide their neighbors
+    fn find_occlusion(&self) -> Vec<BlockSides<FaceShape>> {
+        (0..self.states.count())
+            .map(|state| {
+                let mut data = MeshData::new();
+                let sides = BlockSides::from_fn(|_| true);
+                self.mesh(
+                    &mut data,
+                    BlockPos::new(0, 0, 0),
+                    sides,
+                    state as BlockState,
+                    MeshDetail::default(),
+                );
+                occlusion::face_shapes(&data, self.render_layer == RenderLayer::Opaque)
+            })
+            .collect()
+    }
+
+    /// The colliders of the block in the given state
+    pub fn colliders(&self, state: BlockState) -> Vec<BlockCollider> {
+        match self.states.transform(state) {
+            Some(mat) => self.collider.iter().map(|x| x.transform(mat)).collect(),
+            None => self.collider.clone(),
+        }
+    }
+
+    /// The selection boxes of the block in the given state
+    pub fn selection_boxes(&self, state: BlockState) -> Vec<BlockCollider> {
+        match self.states.transform(state) {
+            Some(mat) => self.selection.iter().map(|x| x.transform(mat)).collect(),
+            None => self.selection.clone(),
+        }
+    }
+}
+
 impl BlockManager {
-    /// Creates a new BlockManager loaded from a json file
-    pub fn new(filename: &str) -> Self {
+    /// Loads the blocks of every pack which has a blocks.json, see `from_files`
+    ///
+    /// The textures are taken from the last pack which has them, even if it doesn't have a
+    /// blocks.json
+    pub fn from_packs(packs: &[String]) -> Result<Self, BlockError> {
+        let files: Vec<String> = packs
+            .iter()
+            .map(|x| format!("{}/blocks.json", x))
+            .filter(|x| std::path::Path::new(x).exists())
+            .collect();
+        if files.is_empty() {
+            let e = std::io::Error::new(std::io::ErrorKind::NotFound, "no pack has a blocks.json");
+            return Err(BlockError::new(BlockErrorKind::Io(e)).in_file("blocks.json"));
+        }
+        Self::load(&files, packs)
+    }
+
+    /// Loads blocks from several files, a block in a later file replaces the block with the same
+    /// name from an earlier one, keeping its ID
+    ///
+    /// Textures are relative to the blocks files, and are also taken from the last file's
+    /// directory which has them
+    pub fn from_files(files: &[String]) -> Result<Self, BlockError> {
+        let dirs: Vec<String> = files
+            .iter()
+            .map(|x| {
+                let dir = std::path::Path::new(x).parent();
+                String::from(dir.and_then(|x| x.to_str()).unwrap_or(""))
+            })
+            .collect();
+        Self::load(files, &dirs)
+    }
+
+    /// Loads blocks from several files, see `from_files`. The textures are searched for in
+    /// `texture_dirs`, the last one which has a texture wins. Textures which aren't in any of them
+    /// are relative to their blocks file.
+    fn load(files: &[String], texture_dirs: &[String]) -> Result<Self, BlockError> {
+        // load json
+        let mut parsed = Vec::new();
+        for filename in files {
+            let blocks_json = std::fs::read_to_string(filename)
+                .map_err(|e| BlockError::new(BlockErrorKind::Io(e)).in_file(filename))?;
+            let blocks_json = json::parse(blocks_json.as_str())
+                .map_err(|e| BlockError::new(BlockErrorKind::Json(e)).in_file(filename))?;
+            if !blocks_json.is_array() {
+                return Err(BlockError::new(BlockErrorKind::NotAnArray).in_file(filename));
+            }
+            parsed.push(blocks_json);
+        }
+
+        // merge the files, every block remembers its file and its index in the file
+        let mut merged: Vec<(usize, usize, &JsonValue)> = Vec::new();
+        for (f, blocks_json) in parsed.iter().enumerate() {
+            for (i, value) in blocks_json.members().enumerate() {
+                let name = value["name"].as_str();
+                let existing = merged
+                    .iter()
+                    .position(|x| name.is_some() && x.2["name"].as_str() == name);
+                match existing {
+                    Some(j) if merged[j].0 == f => {
+                        return Err(BlockError {
+                            filename: files[f].clone(),
+                            block: Some((i, name.map(String::from))),
+                            path: format!("[{}].name", i),
+                            kind: BlockErrorKind::DuplicateName(merged[j].1),
+                        })
+                    }
+                    Some(j) => merged[j] = (f, i, value),
+                    None => merged.push((f, i, value)),
+                }
+            }
+        }
+
+        // load the textures
+        let dirs: Vec<&std::path::Path> = texture_dirs.iter().map(std::path::Path::new).collect();
+        let mut images: Vec<(String, image::RgbaImage)> = Vec::new();
+        // frames, frame time and interpolation of the animated textures
+        let mut animations = Vec::new();
+        let no_atlas = Atlas::new(Vec::new());
+        for &(f, i, value) in &merged {
+            let filename = &files[f];
+            let b = BlockJson {
+                index: i,
+                name: value["name"].as_str(),
+                root: String::new(),
+                atlas: &no_atlas,
+            };
+            let mut names = Vec::new();
+            texture_names(value, String::new(), false, &mut names);
+            for (name, path, texture) in names {
+                if images.iter().any(|x| x.0 == name) {
+                    continue;
+                }
+                // the last pack which has the texture wins
+                let file = dirs
+                    .iter()
+                    .rev()
+                    .map(|x| x.join(&name))
+                    .find(|x| x.exists())
+                    .unwrap_or_else(|| {
+                        let dir = std::path::Path::new(filename).parent();
+                        dir.unwrap_or_else(|| std::path::Path::new("")).join(&name)
+                    });
+                let img = image::open(file)
+                    .map_err(|e| {
+                        b.error(&path, BlockErrorKind::Texture(name.clone(), Box::new(e)))
+                            .in_file(filename)
+                    })?
+                    .to_rgba8();
+                match b.animation(texture, &path, &img) {
+                    Ok(Some((frames, frame_time, interpolate))) => {
+                        images.push((name.clone(), frames[0].clone()));
+                        animations.push((name, frames, frame_time, interpolate));
+                    }
+                    Ok(None) => images.push((name, img)),
+                    Err(e) => return Err(e.in_file(filename)),
+                }
+            }
+        }
+        let mut atlas = Atlas::new(images);
+        for (name, frames, frame_time, interpolate) in animations {
+            atlas.add_animation(&name, frames, frame_time, interpolate);
+        }
         let mut blocks = BlockManager {
             name_index: HashMap::new(),
             blocks: Vec::new(),
+            atlas,
+            behaviors: HashMap::new(),
         };
-        // load json
-        let mut blocks_file =
-            File::open(filename).expect(format!("cannot open file {}", filename).as_str());
-        let mut blocks_json = String::new();
-        blocks_file.read_to_string(&mut blocks_json).unwrap();
-        let blocks_json = json::parse(blocks_json.as_str()).unwrap();
         // parse json
-        for i in 0..blocks_json.len() {
-            blocks.add_block(BlockType::from(&blocks_json[i]));
+        for (f, i, value) in merged {
+            let t =
+                BlockType::from_json(value, i, &blocks.atlas).map_err(|e| e.in_file(&files[f]))?;
+            blocks.add_block(t);
         }
-        blocks
+        Ok(blocks)
+    }
+
+    /// Number of block types
+    pub fn count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Sets the custom logic of the block with the given name, replacing its previous behavior.
+    /// The block doesn't have to exist.
+    pub fn register_behavior(&mut self, name: &str, behavior: Arc<dyn BlockBehavior>) {
+        self.behaviors.insert(String::from(name), behavior);
+    }
+
+    /// The custom logic of a block, None if it doesn't have any
+    pub fn behavior(&self, id: BlockID) -> Option<Arc<dyn BlockBehavior>> {
+        self.behaviors.get(&self.blocks[id as usize].name).cloned()
+    }
+
+    /// The ID of a block, None if there is no block with the name
+    pub fn get(&self, name: &str) -> Option<BlockID> {
+        self.name_index.get(name).copied()
+    }
+
+    /// For every ID of `old`, the ID of the block with the same name in this manager. Blocks which
+    /// don't exist anymore become the first block (air).
+    pub fn remap(&self, old: &BlockManager) -> Vec<BlockID> {
+        old.blocks
+            .iter()
+            .map(|x| self.get(&x.name).unwrap_or(0))
+            .collect()
     }
 
     pub fn add_block(&mut self, t: BlockType) -> BlockID {
@@ -69,126 +523,691 @@ impl std::ops::Index<String> for BlockManager {
     }
 }
 
-impl From<&json::JsonValue> for BlockType {
-    fn from(value: &json::JsonValue) -> Self {
-        // mesh generation function
-        let mesh_type = value["model"].as_str();
-        let mesh_fn: Arc<dyn Fn(&mut MeshData, BlockPos, BlockSides<bool>) + Sync + Send> =
-            match mesh_type {
-                Some(mesh_type) => match mesh_type {
-                    "block" => {
-                        let texture = util::TexRect {
-                            left: value["texture"]["left"].as_i32().unwrap() as f32 / 1024.0,
-                            top: value["texture"]["top"].as_i32().unwrap() as f32 / 1024.0,
-                            right: value["texture"]["right"].as_i32().unwrap() as f32 / 1024.0,
-                            bottom: value["texture"]["bottom"].as_i32().unwrap() as f32 / 1024.0,
-                        };
-                        Arc::new(move |mesh, pos, sides| {
-                            meshgen::append_cube(mesh, pos.into(), sides, texture)
-                        })
-                    }
-                    "sided" => {
-                        let top = util::TexRect {
-                            left: value["textures"]["top"]["left"].as_i32().unwrap() as f32
-                                / 1024.0,
-                            top: value["textures"]["top"]["top"].as_i32().unwrap() as f32 / 1024.0,
-                            right: value["textures"]["top"]["right"].as_i32().unwrap() as f32
-                                / 1024.0,
-                            bottom: value["textures"]["top"]["bottom"].as_i32().unwrap() as f32
-                                / 1024.0,
-                        };
-                        let bottom = util::TexRect {
-                            left: value["textures"]["bottom"]["left"].as_i32().unwrap() as f32
-                                / 1024.0,
-                            top: value["textures"]["bottom"]["top"].as_i32().unwrap() as f32
-                                / 1024.0,
-                            right: value["textures"]["bottom"]["right"].as_i32().unwrap() as f32
-                                / 1024.0,
-                            bottom: value["textures"]["bottom"]["bottom"].as_i32().unwrap() as f32
-                                / 1024.0,
-                        };
-                        let side = util::TexRect {
-                            left: value["textures"]["side"]["left"].as_i32().unwrap() as f32
-                                / 1024.0,
-                            top: value["textures"]["side"]["top"].as_i32().unwrap() as f32 / 1024.0,
-                            right: value["textures"]["side"]["right"].as_i32().unwrap() as f32
-                                / 1024.0,
-                            bottom: value["textures"]["side"]["bottom"].as_i32().unwrap() as f32
-                                / 1024.0,
-                        };
-                        Arc::new(move |mesh, pos, sides| {
-                            meshgen::append_cube_sided(mesh, pos.into(), sides, side, top, bottom)
-                        })
-                    }
-                    "slab" => {
-                        let top = util::TexRect {
-                            left: value["textures"]["top"]["left"].as_i32().unwrap() as f32
-                                / 1024.0,
-                            top: value["textures"]["top"]["top"].as_i32().unwrap() as f32 / 1024.0,
-                            right: value["textures"]["top"]["right"].as_i32().unwrap() as f32
-                                / 1024.0,
-                            bottom: value["textures"]["top"]["bottom"].as_i32().unwrap() as f32
-                                / 1024.0,
-                        };
-                        let bottom = util::TexRect {
-                            left: value["textures"]["bottom"]["left"].as_i32().unwrap() as f32
-                                / 1024.0,
-                            top: value["textures"]["bottom"]["top"].as_i32().unwrap() as f32
-                                / 1024.0,
-                            right: value["textures"]["bottom"]["right"].as_i32().unwrap() as f32
-                                / 1024.0,
-                            bottom: value["textures"]["bottom"]["bottom"].as_i32().unwrap() as f32
-                                / 1024.0,
-                        };
-                        let side = util::TexRect {
-                            left: value["textures"]["side"]["left"].as_i32().unwrap() as f32
-                                / 1024.0,
-                            top: value["textures"]["side"]["top"].as_i32().unwrap() as f32 / 1024.0,
-                            right: value["textures"]["side"]["right"].as_i32().unwrap() as f32
-                                / 1024.0,
-                            bottom: value["textures"]["side"]["bottom"].as_i32().unwrap() as f32
-                                / 1024.0,
-                        };
-                        Arc::new(move |mesh, pos, sides| {
-                            meshgen::append_slab(mesh, pos.into(), sides, side, top, bottom)
-                        })
-                    }
-                    "hedge" => {
-                        let texture = util::TexRect {
-                            left: value["texture"]["left"].as_i32().unwrap() as f32 / 1024.0,
-                            top: value["texture"]["top"].as_i32().unwrap() as f32 / 1024.0,
-                            right: value["texture"]["right"].as_i32().unwrap() as f32 / 1024.0,
-                            bottom: value["texture"]["bottom"].as_i32().unwrap() as f32 / 1024.0,
-                        };
-                        let density = value["density"].as_u32().unwrap();
-                        Arc::new(move |mesh, pos, _| {
-                            meshgen::append_hedge(mesh, pos.into(), texture, density)
-                        })
+/// The image which the old texture rectangles (`{ "left": 16, "top": 0, ... }`) point into
+const LEGACY_TEXTURE: &str = "blocks.png";
+
+/// Finds all of the textures used by a block, together with the path and the json value of
+/// every use
+///
+/// Textures are given by file name, by an object with the file name and an animation, or by a
+/// rectangle in the legacy texture
+fn texture_names<'a>(
+    value: &'a JsonValue,
+    path: String,
+    is_texture: bool,
+    names: &mut Vec<(String, String, &'a JsonValue)>,
+) {
+    if is_texture {
+        if let Some(x) = value.as_str().or_else(|| value["file"].as_str()) {
+            names.push((String::from(x), path, value));
+        } else if value.is_object() {
+            names.push((String::from(LEGACY_TEXTURE), path, value));
+        }
+    } else if value.is_object() {
+        for (k, v) in value.entries() {
+            let p = format!("{}.{}", path, k);
+            if k == "texture" {
+                texture_names(v, p, true, names);
+            } else if k == "textures" && v.is_object() {
+                for (face, t) in v.entries() {
+                    texture_names(t, format!("{}.{}", p, face), true, names);
+                }
+            } else {
+                texture_names(v, p, false, names);
+            }
+        }
+    } else {
+        for (i, v) in value.members().enumerate() {
+            texture_names(v, format!("{}[{}]", path, i), false, names);
+        }
+    }
+}
+
+/// Reads the fields of a single block, remembering which block it is for error messages
+struct BlockJson<'a> {
+    index: usize,
+    name: Option<&'a str>,
+    /// Added in front of every path, used for the variants
+    root: String,
+    atlas: &'a Atlas,
+}
+
+impl BlockJson<'_> {
+    fn error(&self, path: &str, kind: BlockErrorKind) -> BlockError {
+        BlockError {
+            filename: String::new(),
+            block: Some((self.index, self.name.map(String::from))),
+            path: format!("[{}]{}{}", self.index, self.root, path),
+            kind,
+        }
+    }
+
+    /// Reads a required field using `f`, `expected` describes the type for the error message
+    fn field<'v, T>(
+        &self,
+        value: &'v JsonValue,
+        path: &str,
+        expected: &'static str,
+        f: impl Fn(&'v JsonValue) -> Option<T>,
+    ) -> Result<T, BlockError> {
+        if value.is_null() {
+            return Err(self.error(path, BlockErrorKind::Missing(expected)));
+        }
+        f(value).ok_or_else(|| self.error(path, BlockErrorKind::WrongType(expected)))
+    }
+
+    fn i32(&self, value: &JsonValue, path: &str) -> Result<i32, BlockError> {
+        self.field(value, path, "an integer", |x| x.as_i32())
+    }
+
+    fn u32(&self, value: &JsonValue, path: &str) -> Result<u32, BlockError> {
+        self.field(value, path, "a positive integer", |x| x.as_u32())
+    }
+
+    fn f32(&self, value: &JsonValue, path: &str) -> Result<f32, BlockError> {
+        self.field(value, path, "a number", |x| x.as_f32())
+    }
+
+    fn bool(&self, value: &JsonValue, path: &str) -> Result<bool, BlockError> {
+        self.field(value, path, "a boolean", |x| x.as_bool())
+    }
+
+    /// Reads an optional boolean
+    fn bool_or(&self, value: &JsonValue, path: &str, default: bool) -> Result<bool, BlockError> {
+        if value.is_null() {
+            Ok(default)
+        } else {
+            self.bool(value, path)
+        }
+    }
+
+    fn str<'v>(&self, value: &'v JsonValue, path: &str) -> Result<&'v str, BlockError> {
+        self.field(value, path, "a string", |x| x.as_str())
+    }
+
+    fn object<'v>(&self, value: &'v JsonValue, path: &str) -> Result<&'v JsonValue, BlockError> {
+        self.field(value, path, "an object", |x| {
+            Some(x).filter(|x| x.is_object())
+        })
+    }
+
+    /// Reads a texture, either a file name or a rectangle of pixels in the legacy texture
+    fn tex_rect(&self, value: &JsonValue, path: &str) -> Result<util::TexRect, BlockError> {
+        // all of the files have been loaded into the atlas by now
+        if let Some(name) = value.as_str().or_else(|| value["file"].as_str()) {
+            return self
+                .atlas
+                .rect(name, None)
+                .ok_or_else(|| self.error(path, BlockErrorKind::UnknownTexture(name.into())));
+        }
+        let value = self.field(value, path, "a file name or an object", |x| {
+            Some(x).filter(|x| x.is_object())
+        })?;
+        let rect = (
+            self.i32(&value["left"], &format!("{}.left", path))?,
+            self.i32(&value["top"], &format!("{}.top", path))?,
+            self.i32(&value["right"], &format!("{}.right", path))?,
+            self.i32(&value["bottom"], &format!("{}.bottom", path))?,
+        );
+        self.atlas
+            .rect(LEGACY_TEXTURE, Some(rect))
+            .ok_or_else(|| self.error(path, BlockErrorKind::TextureOutOfBounds))
+    }
+
+    fn optional_tex_rect(
+        &self,
+        value: &JsonValue,
+        path: &str,
+    ) -> Result<Option<util::TexRect>, BlockError> {
+        if value.is_null() {
+            Ok(None)
+        } else {
+            self.tex_rect(value, path).map(Some)
+        }
+    }
+
+    /// Reads a box of a `cuboids` model
+    ///
+    /// The textures can be given for every side, `side` is used for the four sides which
+    /// don't have a texture and `all` for everything else. Sides without a texture aren't drawn.
+    fn cuboid(&self, value: &JsonValue, path: &str) -> Result<meshgen::Cuboid, BlockError> {
+        let c = self.collider(value, path)?;
+        let min = Vec3::new(c.x, c.y, c.z);
+
+        let textures_path = format!("{}.textures", path);
+        let textures = self.object(&value["textures"], &textures_path)?;
+        let texture = |name: &str| {
+            self.optional_tex_rect(&textures[name], &format!("{}.{}", textures_path, name))
+        };
+        let all = texture("all")?;
+        let side = texture("side")?.or(all);
+        let textures = util::BlockSides {
+            top: texture("top")?.or(all),
+            bottom: texture("bottom")?.or(all),
+            left: texture("left")?.or(side),
+            right: texture("right")?.or(side),
+            front: texture("front")?.or(side),
+            back: texture("back")?.or(side),
+        };
+
+        // rotation around an axis, by default around the center of the block
+        let rotation = &value["rotation"];
+        let rotation = if rotation.is_null() {
+            None
+        } else {
+            let path = format!("{}.rotation", path);
+            self.object(rotation, &path)?;
+            let angle = self
+                .f32(&rotation["angle"], &format!("{}.angle", path))?
+                .to_radians();
+            let mat = match self.str(&rotation["axis"], &format!("{}.axis", path))? {
+                "x" => Mat4::from_rotation_x(angle),
+                "y" => Mat4::from_rotation_y(angle),
+                "z" => Mat4::from_rotation_z(angle),
+                _ => {
+                    return Err(self.error(
+                        &format!("{}.axis", path),
+                        BlockErrorKind::WrongType("\"x\", \"y\" or \"z\""),
+                    ))
+                }
+            };
+            let origin = &rotation["origin"];
+            let origin = if origin.is_null() {
+                Vec3::new(0.5, 0.5, 0.5)
+            } else {
+                let path = format!("{}.origin", path);
+                self.object(origin, &path)?;
+                Vec3::new(
+                    self.f32(&origin["x"], &format!("{}.x", path))?,
+                    self.f32(&origin["y"], &format!("{}.y", path))?,
+                    self.f32(&origin["z"], &format!("{}.z", path))?,
+                )
+            };
+            Some(Mat4::from_translation(origin) * mat * Mat4::from_translation(-origin))
+        };
+
+        Ok(meshgen::Cuboid {
+            min,
+            max: min + Vec3::new(c.w, c.h, c.d),
+            textures,
+            stretch: self.bool_or(&value["stretch"], &format!("{}.stretch", path), false)?,
+            rotation,
+        })
+    }
+
+    /// Reads the animation of a texture, e.g.
+    /// `{ "file": "water.png", "animation": { "frames": 16, "frame_time": 0.1 } }`
+    ///
+    /// The frames are stacked on top of each other in the image, returns the frames, the frame
+    /// time and whether to interpolate, None if the texture isn't animated
+    #[allow(clippy::type_complexity)]
+    fn animation(
+        &self,
+        value: &JsonValue,
+        path: &str,
+        img: &image::RgbaImage,
+    ) -> Result<Option<(Vec<image::RgbaImage>, f32, bool)>, BlockError> {
+        let animation = &value["animation"];
+        if animation.is_null() {
+            return Ok(None);
+        }
+        let path = format!("{}.animation", path);
+        self.object(animation, &path)?;
+        let frames_path = format!("{}.frames", path);
+        let frames = self.u32(&animation["frames"], &frames_path)?;
+        if frames == 0 || !img.height().is_multiple_of(frames) {
+            return Err(self.error(&frames_path, BlockErrorKind::AnimationFrames));
+        }
+        let frame_time = self.f32(&animation["frame_time"], &format!("{}.frame_time", path))?;
+        if frame_time <= 0.0 {
+            return Err(self.error(
+                &format!("{}.frame_time", path),
+                BlockErrorKind::WrongType("a positive number"),
+            ));
+        }
+        let interpolate = self.bool_or(
+            &animation["interpolate"],
+            &format!("{}.interpolate", path),
+            false,
+        )?;
+        let h = img.height() / frames;
+        let frames = (0..frames)
+            .map(|i| image::imageops::crop_imm(img, 0, i * h, img.width(), h).to_image())
+            .collect();
+        Ok(Some((frames, frame_time, interpolate)))
+    }
+
+    /// Reads the side, top and bottom textures
+    fn sided_textures(
+        &self,
+        value: &JsonValue,
+    ) -> Result<(util::TexRect, util::TexRect, util::TexRect), BlockError> {
+        let textures = self.object(&value["textures"], ".textures")?;
+        Ok((
+            self.tex_rect(&textures["side"], ".textures.side")?,
+            self.tex_rect(&textures["top"], ".textures.top")?,
+            self.tex_rect(&textures["bottom"], ".textures.bottom")?,
+        ))
+    }
+
+    /// The textures of a plain full block (the `block` and `sided` models), None for any other
+    /// model
+    fn cube(&self, value: &JsonValue) -> Result<Option<BlockSides<util::TexRect>>, BlockError> {
+        Ok(match value["model"].as_str() {
+            Some("block") => {
+                let texture = self.tex_rect(&value["texture"], ".texture")?;
+                Some(BlockSides::from_fn(|_| texture))
+            }
+            Some("sided") => {
+                let (side, top, bottom) = self.sided_textures(value)?;
+                Some(BlockSides::from_fn(|x| match x {
+                    BlockSide::Top => top,
+                    BlockSide::Bottom => bottom,
+                    _ => side,
+                }))
+            }
+            _ => None,
+        })
+    }
+
+    /// Reads the model of the block and creates the mesh generation function
+    fn model(&self, value: &JsonValue) -> Result<MeshFn, BlockError> {
+        if value["model"].is_null() {
+            return Ok(Arc::new(|_, _, _, _, _| {}));
+        }
+        Ok(match self.str(&value["model"], ".model")? {
+            "block" => {
+                let texture = self.tex_rect(&value["texture"], ".texture")?;
+                Arc::new(move |mesh, pos, sides, _, _| {
+                    meshgen::append_cube(mesh, pos.into(), sides, texture)
+                })
+            }
+            "sided" => {
+                let (side, top, bottom) = self.sided_textures(value)?;
+                Arc::new(move |mesh, pos, sides, _, _| {
+                    meshgen::append_cube_sided(mesh, pos.into(), sides, side, top, bottom)
+                })
+            }
+            "slab" => {
+                let (side, top, bottom) = self.sided_textures(value)?;
+                Arc::new(move |mesh, pos, sides, _, _| {
+                    meshgen::append_slab(mesh, pos.into(), sides, side, top, bottom)
+                })
+            }
+            "cross" => {
+                let texture = self.tex_rect(&value["texture"], ".texture")?;
+                Arc::new(move |mesh, pos, _, _, _| meshgen::append_cross(mesh, pos.into(), texture))
+            }
+            "cuboids" => {
+                let cuboids_json = self.field(&value["cuboids"], ".cuboids", "an array", |x| {
+                    Some(x).filter(|x| x.is_array())
+                })?;
+                let mut cuboids = Vec::new();
+                for (i, c) in cuboids_json.members().enumerate() {
+                    cuboids.push(self.cuboid(c, &format!(".cuboids[{}]", i))?);
+                }
+                Arc::new(move |mesh, pos, sides, _, _| {
+                    for c in &cuboids {
+                        meshgen::append_cuboid(mesh, pos.into(), &sides, c);
                     }
-                    _ => Arc::new(|_, _, _| {}),
-                },
-                None => Arc::new(|_, _, _| {}),
+                })
+            }
+            "hedge" => {
+                let texture = self.tex_rect(&value["texture"], ".texture")?;
+                let density = self.u32(&value["density"], ".density")?;
+                Arc::new(move |mesh, pos, _, _, detail| {
+                    meshgen::append_hedge(mesh, pos.into(), texture, density, detail)
+                })
+            }
+            x => return Err(self.error(".model", BlockErrorKind::UnknownModel(String::from(x)))),
+        })
+    }
+
+    /// Reads the state properties, e.g. `{ "axis": ["y", "x", "z"] }`
+    fn states(&self, value: &JsonValue) -> Result<StateLayout, BlockError> {
+        let mut states = StateLayout::default();
+        if value.is_null() {
+            return Ok(states);
+        }
+        for (name, values) in self.object(value, ".states")?.entries() {
+            let path = format!(".states.{}", name);
+            let values = self.field(values, &path, "a non-empty array of strings", |x| {
+                let values: Option<Vec<String>> =
+                    x.members().map(|x| x.as_str().map(String::from)).collect();
+                values.filter(|x| !x.is_empty())
+            })?;
+            // the values of these properties have a special meaning
+            let allowed: &[&str] = match name {
+                "axis" => &["x", "y", "z"],
+                "facing" => &["top", "bottom", "left", "right", "front", "back"],
+                "half" => &["bottom", "top"],
+                _ => &[],
             };
+            if let Some(x) = values
+                .iter()
+                .find(|x| !allowed.is_empty() && !allowed.contains(&x.as_str()))
+            {
+                return Err(self.error(&path, BlockErrorKind::UnknownStateValue(x.clone())));
+            }
+            states
+                .add(name, values)
+                .ok_or_else(|| self.error(&path, BlockErrorKind::TooManyStates))?;
+        }
+        Ok(states)
+    }
+
+    fn collider(&self, value: &JsonValue, path: &str) -> Result<BlockCollider, BlockError> {
+        let value = self.object(value, path)?;
+        Ok(BlockCollider {
+            x: self.f32(&value["x"], &format!("{}.x", path))?,
+            y: self.f32(&value["y"], &format!("{}.y", path))?,
+            z: self.f32(&value["z"], &format!("{}.z", path))?,
+            w: self.f32(&value["w"], &format!("{}.w", path))?,
+            h: self.f32(&value["h"], &format!("{}.h", path))?,
+            d: self.f32(&value["d"], &format!("{}.d", path))?,
+        })
+    }
+}
+
+impl BlockJson<'_> {
+    /// The optional tint of a block, `tint` is `"grass"`, `"foliage"` or a constant `[r, g, b]`,
+    /// `tint_faces` can limit it to some of the faces
+    fn tint(&self, value: &JsonValue) -> Result<Option<Tint>, BlockError> {
+        let tint = &value["tint"];
+        if tint.is_null() {
+            return Ok(None);
+        }
+        const EXPECTED: &str = "\"grass\", \"foliage\" or an [r, g, b] array";
+        let source = match tint.as_str() {
+            Some("grass") => TintSource::Grass,
+            Some("foliage") => TintSource::Foliage,
+            Some(_) => return Err(self.error(".tint", BlockErrorKind::WrongType(EXPECTED))),
+            None => {
+                if !tint.is_array() || tint.len() != 3 {
+                    return Err(self.error(".tint", BlockErrorKind::WrongType(EXPECTED)));
+                }
+                TintSource::Constant(Vec3::new(
+                    self.f32(&tint[0], ".tint[0]")?,
+                    self.f32(&tint[1], ".tint[1]")?,
+                    self.f32(&tint[2], ".tint[2]")?,
+                ))
+            }
+        };
+
+        let faces_json = &value["tint_faces"];
+        let faces = if faces_json.is_null() {
+            BlockSides::from_fn(|_| true)
+        } else {
+            self.field(faces_json, ".tint_faces", "an array", |x| {
+                Some(x).filter(|x| x.is_array())
+            })?;
+            let mut faces = Vec::new();
+            for (i, x) in faces_json.members().enumerate() {
+                let path = format!(".tint_faces[{}]", i);
+                let name = self.str(x, &path)?;
+                faces.push(BlockSide::from_name(name).ok_or_else(|| {
+                    self.error(&path, BlockErrorKind::WrongType("the name of a side"))
+                })?);
+            }
+            BlockSides::from_fn(|x| faces.contains(&x))
+        };
+        Ok(Some(Tint { source, faces }))
+    }
+}
+
+impl BlockType {
+    /// Loads a block type from its json description, `index` is the position in the blocks file
+    ///
+    /// The textures have to be in the atlas already
+    pub fn from_json(value: &JsonValue, index: usize, atlas: &Atlas) -> Result<Self, BlockError> {
+        let mut b = BlockJson {
+            index,
+            name: None,
+            root: String::new(),
+            atlas,
+        };
+        b.object(value, "")?;
+        b.name = Some(b.str(&value["name"], ".name")?);
+
+        let states = b.states(&value["states"])?;
+
+        // mesh generation function, every variant can override some of the fields of the block
+        let mut mesh_fn = b.model(value)?;
+        let variants = &value["variants"];
+        if !variants.is_null() {
+            b.object(variants, ".variants")?;
+            let property = states.property("variant").ok_or_else(|| {
+                b.error(".variants", BlockErrorKind::Missing("a \"variant\" state"))
+            })?;
+            let mut meshes = vec![mesh_fn; property.values.len()];
+            for (k, v) in variants.entries() {
+                let path = format!(".variants.{}", k);
+                let i =
+                    property.values.iter().position(|x| x == k).ok_or_else(|| {
+                        b.error(&path, BlockErrorKind::UnknownStateValue(k.into()))
+                    })?;
+                let mut variant = value.clone();
+                for (field, x) in b.object(v, &path)?.entries() {
+                    variant[field] = x.clone();
+                }
+                let vb = BlockJson {
+                    index,
+                    name: b.name,
+                    root: path,
+                    atlas,
+                };
+                meshes[i] = vb.model(&variant)?;
+            }
+            let property = property.clone();
+            mesh_fn = Arc::new(move |mesh, pos, sides, state, detail| {
+                (meshes[property.index(state)])(mesh, pos, sides, state, detail)
+            });
+        }
 
         // load the collider
         let mut collider: Vec<BlockCollider> = Vec::new();
         let collider_json = &value["collider"];
-        for i in 0..collider_json.len() {
-            collider.push(BlockCollider {
-                x: collider_json[i]["x"].as_f32().unwrap(),
-                y: collider_json[i]["y"].as_f32().unwrap(),
-                z: collider_json[i]["z"].as_f32().unwrap(),
-                w: collider_json[i]["w"].as_f32().unwrap(),
-                h: collider_json[i]["h"].as_f32().unwrap(),
-                d: collider_json[i]["d"].as_f32().unwrap(),
-            });
+        if !collider_json.is_null() {
+            b.field(collider_json, ".collider", "an array", |x| {
+                Some(x).filter(|x| x.is_array())
+            })?;
+            for (i, c) in collider_json.members().enumerate() {
+                collider.push(b.collider(c, &format!(".collider[{}]", i))?);
+            }
         }
 
-        BlockType {
+        // the selection boxes are the colliders, or the whole block if it has none
+        let selection_json = &value["selection"];
+        let selection = if !selection_json.is_null() {
+            b.field(selection_json, ".selection", "an array", |x| {
+                Some(x).filter(|x| x.is_array())
+            })?;
+            let mut selection = Vec::new();
+            for (i, c) in selection_json.members().enumerate() {
+                selection.push(b.collider(c, &format!(".selection[{}]", i))?);
+            }
+            selection
+        } else if !collider.is_empty() {
+            collider.clone()
+        } else {
+            vec![BlockCollider {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+                w: 1.0,
+                h: 1.0,
+                d: 1.0,
+            }]
+        };
+
+        // `solid` is the default for opaque, collidable and selectable, blocks with selection
+        // boxes are selectable too
+        let solid = b.bool_or(&value["solid"], ".solid", false)?;
+        let render_layer = if value["render_layer"].is_null() {
+            RenderLayer::Opaque
+        } else {
+            match b.str(&value["render_layer"], ".render_layer")? {
+                "opaque" => RenderLayer::Opaque,
+                "cutout" => RenderLayer::Cutout,
+                "translucent" => RenderLayer::Translucent,
+                _ => {
+                    return Err(b.error(
+                        ".render_layer",
+                        BlockErrorKind::WrongType("\"opaque\", \"cutout\" or \"translucent\""),
+                    ))
+                }
+            }
+        };
+
+        let mut block = BlockType {
             gen_mesh: mesh_fn,
+            // variants can have different models
+            cube: if variants.is_null() {
+                b.cube(value)?
+            } else {
+                None
+            },
             collider,
-            solid: value["solid"].as_bool().unwrap(),
-            name: String::from(value["name"].as_str().unwrap()),
+            selection,
+            opaque: b.bool_or(&value["opaque"], ".opaque", solid)?,
+            occlusion: Vec::new(),
+            collidable: b.bool_or(&value["collidable"], ".collidable", solid)?,
+            selectable: b.bool_or(
+                &value["selectable"],
+                ".selectable",
+                solid || !selection_json.is_null(),
+            )?,
+            falls: b.bool_or(&value["falls"], ".falls", false)?,
+            render_layer,
+            tint: b.tint(value)?,
+            name: String::from(b.name.unwrap()),
+            states,
+        };
+        block.occlusion = block.find_occlusion();
+        Ok(block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mesh_detail_levels_double() {
+        let levels: Vec<u32> = (0..20).map(MeshDetail::level).collect();
+        assert_eq!(
+            levels,
+            [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 3, 3, 3, 3]
+        );
+        assert_eq!(MeshDetail::level(1000), 3);
+        assert_eq!(MeshDetail::default().with_level(2).density, 0.25);
+    }
+
+    #[test]
+    fn mesh_detail_seeds_differ_by_position() {
+        let detail = MeshDetail::default();
+        let a = detail.at(BlockPos::new(1, 2, 3));
+        assert_eq!(a.seed, detail.at(BlockPos::new(1, 2, 3)).seed);
+        assert_ne!(a.seed, detail.at(BlockPos::new(3, 2, 1)).seed);
+        assert_ne!(a.seed, detail.at(BlockPos::new(1, 2, -3)).seed);
+        let other = MeshDetail { seed: 1, ..detail };
+        assert_ne!(a.seed, other.at(BlockPos::new(1, 2, 3)).seed);
+    }
+
+    /// The error kind of loading a block from json
+    fn load_error(value: &str) -> BlockErrorKind {
+        let value = json::parse(value).unwrap();
+        match BlockType::from_json(&value, 3, &Atlas::new(Vec::new())) {
+            Ok(_) => panic!("the block loaded"),
+            Err(e) => {
+                assert_eq!(e.block.map(|x| x.0), Some(3));
+                e.kind
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_state_value() {
+        let kind = load_error(r#"{ "name": "log", "states": { "axis": ["y", "up"] } }"#);
+        assert!(matches!(kind, BlockErrorKind::UnknownStateValue(x) if x == "up"));
+    }
+
+    #[test]
+    fn too_many_states() {
+        let values = r#"["a", "b", "c"]"#;
+        let states: Vec<String> = (0..5).map(|i| format!(r#""p{}": {}"#, i, values)).collect();
+        let json = format!(
+            r#"{{ "name": "x", "states": {{ {} }} }}"#,
+            states.join(", ")
+        );
+        assert!(matches!(load_error(&json), BlockErrorKind::TooManyStates));
+    }
+
+    #[test]
+    fn empty_state_values() {
+        let kind = load_error(r#"{ "name": "x", "states": { "open": [] } }"#);
+        assert!(matches!(kind, BlockErrorKind::WrongType(_)));
+    }
+
+    /// Creates an empty directory for a pack, with a 16x16 stone texture of one color
+    fn pack(name: &str, color: [u8; 4], blocks: Option<&str>) -> String {
+        let dir = std::env::temp_dir().join(format!("unsafe-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("textures")).unwrap();
+        image::RgbaImage::from_pixel(16, 16, image::Rgba(color))
+            .save(dir.join("textures/stone.png"))
+            .unwrap();
+        if let Some(blocks) = blocks {
+            std::fs::write(dir.join("blocks.json"), blocks).unwrap();
         }
+        String::from(dir.to_str().unwrap())
+    }
+
+    /// The color in the middle of a texture in the atlas
+    fn atlas_color(blocks: &BlockManager, name: &str) -> [u8; 4] {
+        let rect = blocks.atlas.rect(name, None).unwrap();
+        let image = &blocks.atlas.image;
+        let x = (rect.left + rect.right) / 2.0 * image.width() as f32;
+        let y = (rect.top + rect.bottom) / 2.0 * image.height() as f32;
+        image.get_pixel(x as u32, y as u32).0
+    }
+
+    const PACK_BLOCKS: &str = r#"[
+        { "name": "air", "solid": false },
+        { "name": "stone", "model": "block", "texture": "textures/stone.png" }
+    ]"#;
+
+    #[test]
+    fn pack_with_only_textures() {
+        let base = pack("textures-base", [255, 0, 0, 255], Some(PACK_BLOCKS));
+        let textures = pack("textures-only", [0, 0, 255, 255], None);
+        let blocks = BlockManager::from_packs(&[base.clone(), textures]).unwrap();
+        assert_eq!(atlas_color(&blocks, "textures/stone.png"), [0, 0, 255, 255]);
+        let blocks = BlockManager::from_packs(&[base]).unwrap();
+        assert_eq!(atlas_color(&blocks, "textures/stone.png"), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn later_pack_replaces_blocks_by_name() {
+        let base = pack("replace-base", [255, 0, 0, 255], Some(PACK_BLOCKS));
+        let blocks = r#"[
+            { "name": "sand", "model": "block", "texture": "textures/stone.png" },
+            { "name": "stone", "model": "block", "texture": "textures/stone.png", "solid": false }
+        ]"#;
+        let other = pack("replace-other", [0, 255, 0, 255], Some(blocks));
+        let blocks = BlockManager::from_packs(&[base, other]).unwrap();
+        assert_eq!(blocks.count(), 3);
+        // the replaced block keeps its ID, new blocks are added after the old ones
+        assert_eq!(blocks.get("stone"), Some(1));
+        assert_eq!(blocks.get("sand"), Some(2));
+        assert!(!blocks[1].collidable);
+    }
+
+    #[test]
+    fn duplicate_name_in_one_pack() {
+        let blocks = r#"[{ "name": "air" }, { "name": "air" }]"#;
+        let dir = pack("duplicate", [0, 0, 0, 255], Some(blocks));
+        let kind = BlockManager::from_packs(&[dir]).err().unwrap().kind;
+        assert!(matches!(kind, BlockErrorKind::DuplicateName(0)));
     }
 }
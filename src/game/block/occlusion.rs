@@ -0,0 +1,155 @@
+use super::*;
+use util::BlockSide;
+
+/// How much of a side of a block is filled, as a 4x4 grid of cells
+///
+/// The cells go along the two other axes in x, y, z order, so the opposite sides of two
+/// neighboring blocks line up.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct FaceShape {
+    /// Cells which are completely hidden behind the side, only for blocks in the opaque layer
+    pub covers: u16,
+    /// Cells which any part of the side's faces lies in
+    pub touches: u16,
+    /// Cells which are completely filled by the side, even if it can be seen through. These hide
+    /// the same translucent block, so there are no faces inside of water.
+    pub fills: u16,
+}
+
+impl FaceShape {
+    /// Whether some of the faces are still visible next to the `other` side
+    pub fn is_visible_next_to(&self, other: &FaceShape) -> bool {
+        self.touches & !other.covers != 0
+    }
+}
+
+/// Finds the shapes of the sides of a block from its mesh, which has to be generated at the
+/// origin with every side visible
+///
+/// Only faces lying flat on the sides of the block and pointing outwards are counted. Covered
+/// cells are found by sampling, so faces don't need to line up with the grid exactly.
+pub fn face_shapes(data: &MeshData, opaque: bool) -> BlockSides<FaceShape> {
+    const EPSILON: f32 = 0.001;
+    // the triangles lying on each side, in the coordinates of the grid
+    let mut triangles: BlockSides<Vec<[(f32, f32); 3]>> = BlockSides::from_fn(|_| Vec::new());
+    for &(a, b, c) in &data.indices {
+        let corners = [a, b, c].map(|i| {
+            let v = data.vertices[i as usize];
+            Vec3::new(v.0, v.1, v.2)
+        });
+        let n = data.normals[a as usize];
+        let normal = Vec3::new(n.0, n.1, n.2);
+        for side in BlockSide::ALL {
+            let dir: Vec3 = side.to_pos().into();
+            let axis = (0..3).find(|x| dir[*x] != 0.0).unwrap();
+            let plane = if dir[axis] > 0.0 { 1.0 } else { 0.0 };
+            if corners.iter().any(|x| (x[axis] - plane).abs() > EPSILON) || normal.dot(dir) < 0.5 {
+                continue;
+            }
+            let (u, v) = grid_axes(axis);
+            let triangle = corners.map(|x| (x[u].clamp(0.0, 1.0), x[v].clamp(0.0, 1.0)));
+            match side {
+                BlockSide::Top => triangles.top.push(triangle),
+                BlockSide::Bottom => triangles.bottom.push(triangle),
+                BlockSide::Left => triangles.left.push(triangle),
+                BlockSide::Right => triangles.right.push(triangle),
+                BlockSide::Front => triangles.front.push(triangle),
+                BlockSide::Back => triangles.back.push(triangle),
+            }
+        }
+    }
+
+    BlockSides::from_fn(|side| {
+        let triangles = triangles.get(side);
+        let mut shape = FaceShape::default();
+        for cell in 0..16 {
+            let (x, y) = ((cell / 4) as f32 * 0.25, (cell % 4) as f32 * 0.25);
+            // the bounding box of a triangle is enough to know if it touches the cell
+            let touched = triangles.iter().any(|t| {
+                let min_u = t.iter().map(|p| p.0).fold(1.0, f32::min);
+                let max_u = t.iter().map(|p| p.0).fold(0.0, f32::max);
+                let min_v = t.iter().map(|p| p.1).fold(1.0, f32::min);
+                let max_v = t.iter().map(|p| p.1).fold(0.0, f32::max);
+                min_u < x + 0.25 - EPSILON
+                    && max_u > x + EPSILON
+                    && min_v < y + 0.25 - EPSILON
+                    && max_v > y + EPSILON
+            });
+            if touched {
+                shape.touches |= 1 << cell;
+            }
+            let covered = (0..5).all(|i| {
+                (0..5).all(|j| {
+                    let p = (
+                        x + EPSILON + i as f32 * (0.25 - 2.0 * EPSILON) / 4.0,
+                        y + EPSILON + j as f32 * (0.25 - 2.0 * EPSILON) / 4.0,
+                    );
+                    triangles.iter().any(|t| in_triangle(p, t))
+                })
+            });
+            if covered {
+                shape.fills |= 1 << cell;
+            }
+            if opaque && covered {
+                shape.covers |= 1 << cell;
+            }
+        }
+        shape
+    })
+}
+
+/// The two axes along a side which points along `axis`
+fn grid_axes(axis: usize) -> (usize, usize) {
+    match axis {
+        0 => (1, 2),
+        1 => (0, 2),
+        _ => (0, 1),
+    }
+}
+
+/// Whether a point is inside a triangle or on its edges, in either winding order
+fn in_triangle(p: (f32, f32), t: &[(f32, f32); 3]) -> bool {
+    let edge = |a: (f32, f32), b: (f32, f32)| (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0);
+    let d = [edge(t[0], t[1]), edge(t[1], t[2]), edge(t[2], t[0])];
+    let has_neg = d.iter().any(|x| *x < -1e-6);
+    let has_pos = d.iter().any(|x| *x > 1e-6);
+    !(has_neg && has_pos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cube_covers_every_side() {
+        let shapes = face_shapes(&MeshData::cube(), true);
+        for side in BlockSide::ALL {
+            let shape = shapes.get(side);
+            assert_eq!((shape.covers, shape.touches), (u16::MAX, u16::MAX));
+        }
+        let see_through = face_shapes(&MeshData::cube(), false);
+        for side in BlockSide::ALL {
+            let shape = see_through.get(side);
+            assert_eq!((shape.covers, shape.fills), (0, u16::MAX));
+        }
+    }
+
+    #[test]
+    fn slab_covers_the_bottom_half() {
+        let mut slab = MeshData::cube();
+        for v in &mut slab.vertices {
+            v.1 *= 0.5;
+        }
+        let shapes = face_shapes(&slab, true);
+        assert_eq!(shapes.bottom.covers, u16::MAX);
+        assert_eq!(shapes.top.touches, 0);
+        // the cells of the sides go along y first
+        assert_eq!(shapes.left.covers, 0x00ff);
+        assert_eq!(shapes.left.touches, 0x00ff);
+        // two slabs next to each other hide each other's sides, but not a full block's side
+        assert!(!shapes.left.is_visible_next_to(&shapes.right));
+        let cube = face_shapes(&MeshData::cube(), true);
+        assert!(cube.left.is_visible_next_to(&shapes.right));
+        assert!(!shapes.left.is_visible_next_to(&cube.right));
+    }
+}
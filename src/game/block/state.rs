@@ -0,0 +1,226 @@
+use super::*;
+use util::BlockSide;
+
+/// Extra data stored alongside every block, packs the values of all of the block's properties
+///
+/// The first value of every property is stored as 0, so a state of 0 is always the default state
+pub type BlockState = u8;
+
+/// A property of a block, declared in blocks.json like `"axis": ["y", "x", "z"]`
+///
+/// `axis`, `facing` and `half` rotate or flip the model and the collider, and are chosen when the
+/// block is placed. `variant` can change the model, see `BlockType::from_json`. `persistent` is set
+/// to `true` when a player places the block, e.g. leaves which aren't part of a tree. Any other
+/// property is just stored.
+#[derive(Clone)]
+pub struct StateProperty {
+    pub name: String,
+    pub values: Vec<String>,
+    /// Position of the property in the `BlockState`
+    shift: u8,
+    mask: u8,
+}
+
+impl StateProperty {
+    /// The index of the value of the property in a given state
+    pub fn index(&self, state: BlockState) -> usize {
+        (((state >> self.shift) & self.mask) as usize).min(self.values.len() - 1)
+    }
+}
+
+/// Describes how the properties of a block type are packed into a `BlockState`
+#[derive(Default)]
+pub struct StateLayout {
+    pub properties: Vec<StateProperty>,
+}
+
+impl StateLayout {
+    /// How many bits can be used by all of the properties of a block
+    pub const BITS: u32 = BlockState::BITS;
+
+    /// Adds a property, returns None if it doesn't fit into a `BlockState`
+    pub fn add(&mut self, name: &str, values: Vec<String>) -> Option<()> {
+        let shift: u32 = self.properties.iter().map(|x| x.mask.count_ones()).sum();
+        // how many bits are needed to store every value
+        let bits = usize::BITS - (values.len().max(2) - 1).leading_zeros();
+        if shift + bits > Self::BITS {
+            return None;
+        }
+        self.properties.push(StateProperty {
+            name: String::from(name),
+            values,
+            shift: shift as _,
+            mask: ((1u32 << bits) - 1) as _,
+        });
+        Some(())
+    }
+
+    /// How many different states there are, including ones with values which don't exist
+    pub fn count(&self) -> usize {
+        let bits: u32 = self.properties.iter().map(|x| x.mask.count_ones()).sum();
+        1 << bits
+    }
+
+    pub fn property(&self, name: &str) -> Option<&StateProperty> {
+        self.properties.iter().find(|x| x.name == name)
+    }
+
+    /// The value of a property, None if the block doesn't have the property
+    pub fn get(&self, state: BlockState, name: &str) -> Option<&str> {
+        let p = self.property(name)?;
+        Some(p.values[p.index(state)].as_str())
+    }
+
+    /// Changes the value of a property, does nothing if the property or the value doesn't exist
+    pub fn set(&self, state: BlockState, name: &str, value: &str) -> BlockState {
+        match self.property(name) {
+            Some(p) => match p.values.iter().position(|x| x == value) {
+                Some(i) => (state & !(p.mask << p.shift)) | ((i as u8) << p.shift),
+                None => state,
+            },
+            None => state,
+        }
+    }
+
+    /// Converts a state into another layout by the names of the properties and their values, the
+    /// properties which the other layout doesn't have are dropped
+    pub fn convert(&self, state: BlockState, to: &StateLayout) -> BlockState {
+        self.properties
+            .iter()
+            .fold(0, |x, p| to.set(x, &p.name, &p.values[p.index(state)]))
+    }
+
+    /// The transformation of the model and colliders in a given state, in block-local
+    /// coordinates. None if the block isn't transformed at all.
+    pub fn transform(&self, state: BlockState) -> Option<Mat4> {
+        let mut mat = Mat4::IDENTITY;
+        // the model's front side gets turned towards `facing`
+        match self.get(state, "facing") {
+            Some("right") => mat *= Mat4::from_rotation_y(std::f32::consts::FRAC_PI_2),
+            Some("back") => mat *= Mat4::from_rotation_y(std::f32::consts::PI),
+            Some("left") => mat *= Mat4::from_rotation_y(-std::f32::consts::FRAC_PI_2),
+            Some("top") => mat *= Mat4::from_rotation_x(-std::f32::consts::FRAC_PI_2),
+            Some("bottom") => mat *= Mat4::from_rotation_x(std::f32::consts::FRAC_PI_2),
+            _ => (),
+        }
+        // the model's y axis gets turned towards `axis`
+        match self.get(state, "axis") {
+            Some("x") => mat *= Mat4::from_rotation_z(-std::f32::consts::FRAC_PI_2),
+            Some("z") => mat *= Mat4::from_rotation_x(std::f32::consts::FRAC_PI_2),
+            _ => (),
+        }
+        // the model is flipped upside down
+        if let Some("top") = self.get(state, "half") {
+            mat *= Mat4::from_scale(Vec3::new(1.0, -1.0, 1.0));
+        }
+        if mat == Mat4::IDENTITY {
+            return None;
+        }
+        let center = Vec3::new(0.5, 0.5, 0.5);
+        Some(Mat4::from_translation(center) * mat * Mat4::from_translation(-center))
+    }
+
+    /// Chooses the state of a newly placed block
+    ///
+    /// `face` is the side of the new block which was clicked (the one touching the block we were
+    /// looking at), `look` is the direction the player is looking in and `hit` is the point where
+    /// the ray hit the block.
+    pub fn placement_state(&self, face: BlockSide, look: Vec3, hit: Vec3) -> BlockState {
+        let mut state = 0;
+        let axis = match face {
+            BlockSide::Left | BlockSide::Right => "x",
+            BlockSide::Top | BlockSide::Bottom => "y",
+            BlockSide::Front | BlockSide::Back => "z",
+        };
+        state = self.set(state, "axis", axis);
+
+        // face the player, if the block can face up or down, use the vertical direction if the
+        // player is looking more up or down than forward
+        let can_face_vertically = self
+            .property("facing")
+            .is_some_and(|p| p.values.iter().any(|x| x == "top" || x == "bottom"));
+        let facing = if can_face_vertically && look.y.abs() > look.x.abs().max(look.z.abs()) {
+            if look.y > 0.0 {
+                BlockSide::Bottom
+            } else {
+                BlockSide::Top
+            }
+        } else if look.x.abs() > look.z.abs() {
+            if look.x > 0.0 {
+                BlockSide::Left
+            } else {
+                BlockSide::Right
+            }
+        } else if look.z > 0.0 {
+            BlockSide::Back
+        } else {
+            BlockSide::Front
+        };
+        state = self.set(state, "facing", facing.name());
+
+        let half = match face {
+            BlockSide::Top => "top",
+            BlockSide::Bottom => "bottom",
+            _ if hit.y - hit.y.floor() > 0.5 => "top",
+            _ => "bottom",
+        };
+        state = self.set(state, "half", half);
+        self.set(state, "persistent", "true")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(properties: &[(&str, &[&str])]) -> StateLayout {
+        let mut layout = StateLayout::default();
+        for (name, values) in properties {
+            layout
+                .add(name, values.iter().map(|x| String::from(*x)).collect())
+                .unwrap();
+        }
+        layout
+    }
+
+    #[test]
+    fn set_and_get() {
+        let l = layout(&[("axis", &["y", "x", "z"]), ("open", &["false", "true"])]);
+        assert_eq!(l.count(), 8);
+        assert_eq!(l.get(0, "axis"), Some("y"));
+        assert_eq!(l.get(0, "open"), Some("false"));
+        let state = l.set(l.set(0, "axis", "z"), "open", "true");
+        assert_eq!(l.get(state, "axis"), Some("z"));
+        assert_eq!(l.get(state, "open"), Some("true"));
+        let state = l.set(state, "axis", "x");
+        assert_eq!(l.get(state, "axis"), Some("x"));
+        assert_eq!(l.get(state, "open"), Some("true"));
+        // missing properties and values are ignored
+        assert_eq!(l.set(state, "axis", "w"), state);
+        assert_eq!(l.set(state, "half", "top"), state);
+        assert_eq!(l.get(state, "half"), None);
+    }
+
+    #[test]
+    fn add_fails_when_full() {
+        let values = &["a", "b", "c", "d"];
+        let mut l = layout(&[("a", values), ("b", values), ("c", values), ("d", values)]);
+        assert!(l
+            .add("e", vec![String::from("x"), String::from("y")])
+            .is_none());
+    }
+
+    #[test]
+    fn convert_by_names() {
+        let from = layout(&[("half", &["bottom", "top"]), ("axis", &["y", "x", "z"])]);
+        let to = layout(&[("axis", &["x", "y", "z"]), ("open", &["false", "true"])]);
+        let state = from.set(from.set(0, "half", "top"), "axis", "z");
+        let converted = from.convert(state, &to);
+        assert_eq!(to.get(converted, "axis"), Some("z"));
+        assert_eq!(to.get(converted, "open"), Some("false"));
+        // values which don't exist anymore become the default
+        let state = to.set(0, "axis", "y");
+        let back = layout(&[("axis", &["x", "z"])]);
+        assert_eq!(back.get(to.convert(state, &back), "axis"), Some("x"));
+    }
+}
@@ -11,22 +11,23 @@ fn overlap(aa: f32, ab: f32, ba: f32, bb: f32) -> bool {
         || (bb < ab - TOLERANCE && bb - TOLERANCE > aa)
 }
 
-fn collide(player: &Player, collider: BlockCollider) -> Option<(BlockSide, f32, f32)> {
+/// Collides a box standing at `pos` (the center of its bottom) with a collider
+fn collide(
+    pos: Vec3,
+    radius: f32,
+    height: f32,
+    collider: BlockCollider,
+) -> Option<(BlockSide, f32, f32)> {
     let colliding_x = overlap(
-        player.pos.x - Player::RADIUS,
-        player.pos.x + Player::RADIUS,
+        pos.x - radius,
+        pos.x + radius,
         collider.x,
         collider.x + collider.w,
     );
-    let colliding_y = overlap(
-        player.pos.y,
-        player.pos.y + Player::HEIGHT,
-        collider.y,
-        collider.y + collider.h,
-    );
+    let colliding_y = overlap(pos.y, pos.y + height, collider.y, collider.y + collider.h);
     let colliding_z = overlap(
-        player.pos.z - Player::RADIUS,
-        player.pos.z + Player::RADIUS,
+        pos.z - radius,
+        pos.z + radius,
         collider.z,
         collider.z + collider.d,
     );
@@ -36,45 +37,45 @@ fn collide(player: &Player, collider: BlockCollider) -> Option<(BlockSide, f32,
     }
 
     if colliding_y && colliding_z {
-        Some(if player.pos.x > collider.x {
+        Some(if pos.x > collider.x {
             (
                 BlockSide::Right,
-                player.pos.x - Player::RADIUS - collider.x - collider.w,
-                collider.x + collider.w + Player::RADIUS,
+                pos.x - radius - collider.x - collider.w,
+                collider.x + collider.w + radius,
             )
         } else {
             (
                 BlockSide::Left,
-                collider.x - player.pos.x - Player::RADIUS,
-                collider.x - Player::RADIUS,
+                collider.x - pos.x - radius,
+                collider.x - radius,
             )
         })
     } else if colliding_x && colliding_z {
-        Some(if player.pos.y > collider.y {
+        Some(if pos.y > collider.y {
             (
                 BlockSide::Top,
-                player.pos.y - collider.y - collider.h,
+                pos.y - collider.y - collider.h,
                 collider.y + collider.h,
             )
         } else {
             (
                 BlockSide::Bottom,
-                collider.y - player.pos.y - Player::HEIGHT,
-                collider.y - Player::HEIGHT,
+                collider.y - pos.y - height,
+                collider.y - height,
             )
         })
     } else if colliding_x && colliding_y {
-        Some(if player.pos.z > collider.z {
+        Some(if pos.z > collider.z {
             (
                 BlockSide::Front,
-                player.pos.z - Player::RADIUS - collider.z - collider.d,
-                collider.z + collider.d + Player::RADIUS,
+                pos.z - radius - collider.z - collider.d,
+                collider.z + collider.d + radius,
             )
         } else {
             (
                 BlockSide::Back,
-                collider.z - player.pos.z - Player::RADIUS,
-                collider.z - Player::RADIUS,
+                collider.z - pos.z - radius,
+                collider.z - radius,
             )
         })
     } else {
@@ -82,27 +83,37 @@ fn collide(player: &Player, collider: BlockCollider) -> Option<(BlockSide, f32,
     }
 }
 
-pub fn check_collisions(player: &mut Player, game: &mut Game) -> BlockSides<Option<(f32, f32)>> {
-    let player_pos = BlockPos::new(
-        player.pos.x.floor() as _,
-        player.pos.y.floor() as _,
-        player.pos.z.floor() as _,
-    );
+/// Finds the closest collider on every side of a box standing at `pos` (the center of its
+/// bottom), used for the player and the falling blocks
+///
+/// Every side contains the distance to the collider and the position which the box should be
+/// moved to when it touches it
+pub fn check_collisions(
+    world: &mut ChunkServer,
+    pos: Vec3,
+    radius: f32,
+    height: f32,
+) -> BlockSides<Option<(f32, f32)>> {
+    let center = BlockPos::new(pos.x.floor() as _, pos.y.floor() as _, pos.z.floor() as _);
+    let blocks = world.block_manager.clone();
 
     let mut distances = std::collections::HashMap::<BlockSide, (f32, f32)>::new();
 
-    let block_radius: i32 = Player::RADIUS.ceil() as _;
-    let block_height: i32 = Player::HEIGHT.ceil() as _;
+    let block_radius: i32 = radius.ceil() as _;
+    let block_height: i32 = height.ceil() as _;
 
     // get all colliders surrouding the player
-    for i in (player_pos.x - block_radius)..(player_pos.x + block_radius + 1) {
-        for j in (player_pos.y - 1)..(player_pos.y + block_height + 2) {
-            for k in (player_pos.z - block_radius)..(player_pos.z + block_radius + 1) {
-                let pos = BlockPos::new(i, j, k);
-                if let Some(b) = game.chunks.get_block(pos) {
-                    for l in &game.blocks[b].collider {
+    for i in (center.x - block_radius)..(center.x + block_radius + 1) {
+        for j in (center.y - 1)..(center.y + block_height + 2) {
+            for k in (center.z - block_radius)..(center.z + block_radius + 1) {
+                let block_pos = BlockPos::new(i, j, k);
+                if let Some(b) = world.get_block(block_pos).filter(|x| blocks[*x].collidable) {
+                    let state = world.get_state(block_pos).unwrap_or(0);
+                    for l in &blocks[b].colliders(state) {
                         let result = collide(
-                            player,
+                            pos,
+                            radius,
+                            height,
                             BlockCollider {
                                 x: l.x + i as f32,
                                 y: l.y + j as f32,
@@ -163,15 +174,7 @@ pub fn check_collisions(player: &mut Player, game: &mut Game) -> BlockSides<Opti
 //             ),
 //         ];
 //         for i in cases {
-//             let player = Player {
-//                 pos: i.0,
-//                 rotation: Vec2::new(0.0, 0.0),
-//                 velocity: Vec3::new(0.0, 0.0, 0.0),
-//                 selected_block: 0,
-//                 hotbar: Vec::new(),
-//                 item_models: Vec::new(),
-//             };
-//             collide(&player, i.1);
+//             collide(i.0, Player::RADIUS, Player::HEIGHT, i.1);
 //         }
 //     }
 // }
@@ -10,7 +10,8 @@ pub struct InputState {
     /// Keys which have been released in this frame
     pub keys_released: std::collections::HashSet<glutin::event::VirtualKeyCode>,
 
-    /// The five mouse buttons (Left, Middle, Right + Scroll wheel)
+    /// The five mouse buttons (Left, Middle, Right + Scroll wheel up and down) which have been
+    /// pressed in this frame
     pub pressed_buttons: [bool; 5],
 
     pub cursor: Vec2,
@@ -45,7 +46,7 @@ impl InputState {
             } => {
                 let code = input
                     .virtual_keycode
-                    .unwrap_or_else(|| glutin::event::VirtualKeyCode::Space);
+                    .unwrap_or(glutin::event::VirtualKeyCode::Space);
                 match input.state {
                     glutin::event::ElementState::Pressed => {
                         self.keys_down.insert(code);
@@ -57,6 +58,27 @@ impl InputState {
                     }
                 }
             }
+            glutin::event::WindowEvent::MouseInput {
+                state: glutin::event::ElementState::Pressed,
+                button,
+                ..
+            } => match button {
+                glutin::event::MouseButton::Left => self.pressed_buttons[0] = true,
+                glutin::event::MouseButton::Middle => self.pressed_buttons[1] = true,
+                glutin::event::MouseButton::Right => self.pressed_buttons[2] = true,
+                _ => (),
+            },
+            glutin::event::WindowEvent::MouseWheel { delta, .. } => {
+                let y = match delta {
+                    glutin::event::MouseScrollDelta::LineDelta(_, y) => *y,
+                    glutin::event::MouseScrollDelta::PixelDelta(p) => p.y as f32,
+                };
+                if y > 0.0 {
+                    self.pressed_buttons[3] = true;
+                } else if y < 0.0 {
+                    self.pressed_buttons[4] = true;
+                }
+            }
             glutin::event::WindowEvent::CursorMoved {
                 device_id: _,
                 position,
@@ -87,6 +109,7 @@ impl InputState {
     pub fn reset(&mut self) {
         self.keys_pressed.clear();
         self.keys_released.clear();
+        self.pressed_buttons = [false; 5];
         self.cursor = Vec2::new(0.0, 0.0);
     }
 }
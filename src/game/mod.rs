@@ -1,4 +1,5 @@
 mod block;
+mod collisions;
 mod input;
 mod player;
 mod raycast;
@@ -20,7 +21,6 @@ use util::BlockPos;
 use world::*;
 
 pub struct Game {
-    pub input: InputState,
     pub blocks: Arc<BlockManager>,
     pub chunks: ChunkServer,
     pub config: json::JsonValue,
@@ -35,12 +35,24 @@ impl Game {
             config_file.read_to_string(&mut config_json).unwrap();
             json::parse(config_json.as_str()).unwrap()
         };
-        let manager = Arc::new(BlockManager::new("blocks.json"));
+        let packs = Self::packs(&config);
+        let manager = match Self::load_blocks(&packs) {
+            Ok(x) => Arc::new(x),
+            Err(e) => {
+                println!("{}", e);
+                std::process::exit(1);
+            }
+        };
+        Self::record_packs(&config, &packs);
         let clone = manager.clone();
-        let chunks = ChunkServer::new(Rc::new(Texture::load("blocks.png")), clone, &config);
+        let texture = Rc::new(Texture::from_image(
+            &manager.atlas.image,
+            block::Atlas::MIP_LEVELS,
+        ));
+        let mut chunks = ChunkServer::new(texture, clone, &config);
+        tick::register_defaults(&mut chunks);
 
         let mut game = Game {
-            input: InputState::new(),
             blocks: manager,
             chunks,
             config,
@@ -48,6 +60,106 @@ impl Game {
         game.chunks.update(BlockPos::new(0, 0, 0));
         game
     }
+
+    /// Loads the blocks of the packs and registers their behaviors
+    fn load_blocks(packs: &[String]) -> Result<BlockManager, block::BlockError> {
+        let mut blocks = BlockManager::from_packs(packs)?;
+        block::behavior::register_defaults(&mut blocks);
+        Ok(blocks)
+    }
+
+    /// The pack directories from the config, later packs override earlier ones
+    fn packs(config: &json::JsonValue) -> Vec<String> {
+        if config["packs"].is_null() {
+            return vec![String::from(".")];
+        }
+        config["packs"]
+            .members()
+            .map(|x| String::from(x.as_str().expect("packs have to be strings")))
+            .collect()
+    }
+
+    /// Writes the list of packs into the save directory, so we know what the world was made with
+    fn record_packs(config: &json::JsonValue, packs: &[String]) {
+        let save_dir = config["save_dir"].as_str().unwrap();
+        let _ = std::fs::create_dir_all(save_dir);
+        let list = json::JsonValue::from(packs.to_vec());
+        if let Err(e) = std::fs::write(format!("{}/packs.json", save_dir), list.pretty(2)) {
+            println!("cannot record the packs in {}: {}", save_dir, e);
+        }
+    }
+
+    /// Where the shaders are loaded from, `shader_dir` and then the `shaders` directory of every
+    /// pack
+    pub fn shader_dirs(&self) -> Vec<String> {
+        let mut dirs = vec![String::from(
+            self.config["shader_dir"]
+                .as_str()
+                .unwrap_or("src/game/shaders"),
+        )];
+        dirs.extend(
+            Self::packs(&self.config)
+                .iter()
+                .map(|x| format!("{}/shaders", x)),
+        );
+        dirs
+    }
+
+    /// Loads the blocks and textures of the packs again and remeshes everything
+    ///
+    /// If the new blocks can't be loaded, the old ones are kept
+    pub fn reload_blocks(&mut self, player: &mut Player) {
+        let manager = match Self::load_blocks(&Self::packs(&self.config)) {
+            Ok(x) => Arc::new(x),
+            Err(e) => {
+                println!("cannot reload blocks: {}", e);
+                return;
+            }
+        };
+        let texture = Rc::new(Texture::from_image(
+            &manager.atlas.image,
+            block::Atlas::MIP_LEVELS,
+        ));
+        player.reload_blocks(&self.blocks, &manager);
+        self.chunks.reload_blocks(manager.clone(), texture);
+        self.blocks = manager;
+    }
+}
+
+/// Compiles a shader, exits if it fails
+fn startup_shader(shader: Result<Shader, String>) -> Shader {
+    match shader {
+        Ok(x) => x,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Replaces the shader of a view with a newly compiled one, keeps the old one if it fails
+fn reload_shader(view: &mut RenderView, shader: Result<Shader, String>) {
+    match shader {
+        Ok(x) => *view = RenderView::new(x),
+        Err(e) => println!("cannot reload shader: {}", e),
+    }
+}
+
+/// Loads blocks files without starting the game and reports any errors, later files override
+/// earlier ones like packs do
+///
+/// Returns whether the files are valid
+pub fn check_blocks(files: &[String]) -> bool {
+    match BlockManager::from_files(files) {
+        Ok(blocks) => {
+            println!("{}: {} blocks OK", files.join(", "), blocks.count());
+            true
+        }
+        Err(e) => {
+            println!("{}", e);
+            false
+        }
+    }
 }
 
 pub fn start() {
@@ -77,21 +189,21 @@ pub fn start() {
         glBlendFunc(gl33::GL_SRC_ALPHA, gl33::GL_ONE_MINUS_SRC_ALPHA);
     }
 
-    let mut game_view = RenderView::new(shaders::game_shader());
-    let mut ui_view = RenderView::new(shaders::ui_shader());
-
     let mut game = Game::new();
+    let mut game_view = RenderView::new(startup_shader(shaders::game_shader(&game.shader_dirs())));
+    let mut ui_view = RenderView::new(startup_shader(shaders::ui_shader(&game.shader_dirs())));
     let mut player = Player::new(Vec3::new(0.0, 10.0, 0.0), &game);
     let mut input_state = InputState::new();
 
     let mut frames_since_message = 0;
     let mut last_time = std::time::Instant::now();
+    let start_time = std::time::Instant::now();
 
     evloop.run(move |ev, _, control_flow| {
         *control_flow = glutin::event_loop::ControlFlow::Wait;
 
         match ev {
-            glutin::event::Event::LoopDestroyed => return,
+            glutin::event::Event::LoopDestroyed => (),
             glutin::event::Event::WindowEvent { event, .. } => {
                 if input_state.handle_input(&event, &context) {
                     *control_flow = glutin::event_loop::ControlFlow::Exit;
@@ -100,8 +212,18 @@ pub fn start() {
                 if let glutin::event::WindowEvent::CursorMoved { device_id: _, .. } = event {}
             }
             glutin::event::Event::MainEventsCleared => unsafe {
+                if input_state
+                    .keys_pressed
+                    .contains(&glutin::event::VirtualKeyCode::F5)
+                {
+                    game.reload_blocks(&mut player);
+                    reload_shader(&mut game_view, shaders::game_shader(&game.shader_dirs()));
+                    reload_shader(&mut ui_view, shaders::ui_shader(&game.shader_dirs()));
+                }
                 player.update(&input_state, &mut game);
+                game.chunks.update_falling_blocks();
 
+                game.chunks.run_ticks(start_time.elapsed().as_secs_f32());
                 game.chunks.update(BlockPos::new(
                     player.pos.x as _,
                     player.pos.y as _,
@@ -116,14 +238,21 @@ pub fn start() {
                     let size = context.window().inner_size();
                     size.width as f32 / size.height as f32
                 };
-                let mat = glam::Mat4::perspective_lh(1.0, aspect, 0.1, 1000.0)
+                let far = (game.chunks.draw_distance() * 1.5).max(1000.0);
+                let mat = glam::Mat4::perspective_lh(1.0, aspect, 0.1, far)
                     * glam::Mat4::from_rotation_x(-player.rotation.x)
                     * glam::Mat4::from_rotation_y(-player.rotation.y);
-                game_view
-                    .set_camera_position(player.pos + Vec3::new(0.0, Player::CAMERA_HEIGHT, 0.0));
+                let camera = player.pos + Vec3::new(0.0, Player::CAMERA_HEIGHT, 0.0);
+                game_view.set_camera_position(camera);
                 game_view.set_view(mat);
                 game_view.set_model(glam::Mat4::IDENTITY);
-                game.chunks.render();
+                game.chunks
+                    .animate_textures(start_time.elapsed().as_secs_f32());
+                // falling blocks are opaque, they have to be drawn before the translucent meshes
+                game.chunks.render_falling_blocks(&mut game_view);
+                let chunk_counts = game
+                    .chunks
+                    .render(&mut game_view, &util::Frustum::new(mat, camera));
 
                 // now, render the UI
                 glClear(gl33::GL_DEPTH_BUFFER_BIT);
@@ -138,11 +267,18 @@ pub fn start() {
                 if frames_since_message >= 60 {
                     let now = std::time::Instant::now();
                     println!(
-                        "{:.2} FPS\t position: {} {} {}",
+                        "{:.2} FPS\t position: {} {} {}\t chunk memory: {:.1} MiB ({:.1} MiB of mesh buffers)\t vertices: {}\t chunks drawn: {}/{}\t far terrain: {} tiles ({:.1} MiB)",
                         60000.0 / now.duration_since(last_time).as_millis() as f32,
                         player.pos.x,
                         player.pos.y,
-                        player.pos.z
+                        player.pos.z,
+                        game.chunks.memory_usage() as f32 / (1024.0 * 1024.0),
+                        game.chunks.mesh_arena().capacity() as f32 / (1024.0 * 1024.0),
+                        game.chunks.vertex_count(),
+                        chunk_counts.0,
+                        chunk_counts.1,
+                        game.chunks.far_terrain().tile_count(),
+                        game.chunks.far_terrain().memory_usage() as f32 / (1024.0 * 1024.0)
                     );
                     last_time = now;
                     frames_since_message = 0;
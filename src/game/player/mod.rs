@@ -3,14 +3,14 @@ use crate::game::util::BlockSides;
 use block::BlockID;
 use glutin::event::VirtualKeyCode;
 
-mod collisions;
-
 pub struct Player {
     pub pos: Vec3,
     pub rotation: Vec2,
     velocity: Vec3,
 
     selected_block: usize,
+    /// How far away the player can reach blocks
+    reach: f32,
 
     hotbar: Vec<BlockID>,
     item_models: Vec<Model>,
@@ -33,17 +33,32 @@ impl Player {
             game.blocks[String::from("log")],
             game.blocks[String::from("slab")],
             game.blocks[String::from("leaves")],
+            game.blocks[String::from("sand")],
         ];
+        let item_models = Self::item_models(&hotbar, &game.blocks);
+        Player {
+            pos,
+            rotation: Vec2::new(0.0, 0.0),
+            velocity: Vec3::new(0.0, 0.0, 0.0),
+            selected_block: 3,
+            reach: game.config["reach"].as_f32().unwrap_or(8.0),
+            hotbar,
+            item_models,
+        }
+    }
+
+    /// Generates the models of the blocks in the hotbar
+    fn item_models(hotbar: &[BlockID], blocks: &BlockManager) -> Vec<Model> {
         let item_mat = Mat4::from_translation(Vec3::new(-0.8, 0.8, 0.0))
             * Mat4::from_scale(Vec3::new(0.15, 0.15, 0.15))
             * Mat4::from_translation(Vec3::new(0.0, -f32::sqrt(2.0) / 2.0, 0.0))
             * Mat4::from_rotation_x(-std::f32::consts::FRAC_PI_4)
             * Mat4::from_rotation_y(-std::f32::consts::FRAC_PI_4);
-        let item_models = hotbar
+        hotbar
             .iter()
             .map(|x| {
                 let mut data = MeshData::new();
-                (game.blocks[*x as BlockID].gen_mesh)(
+                blocks[*x].mesh(
                     &mut data,
                     BlockPos::new(0, 0, 0),
                     BlockSides {
@@ -54,6 +69,8 @@ impl Player {
                         front: true,
                         back: true,
                     },
+                    0,
+                    block::MeshDetail::default(),
                 );
                 for i in &mut data.vertices {
                     let vec = item_mat.transform_point3(Vec3::new(i.0, i.1, i.2));
@@ -61,19 +78,20 @@ impl Player {
                 }
                 Model::new(&data)
             })
-            .collect();
-        Player {
-            pos,
-            rotation: Vec2::new(0.0, 0.0),
-            velocity: Vec3::new(0.0, 0.0, 0.0),
-            selected_block: 3,
-            hotbar,
-            item_models,
+            .collect()
+    }
+
+    /// Matches the hotbar to reloaded block types and regenerates the item models
+    pub fn reload_blocks(&mut self, old: &BlockManager, new: &BlockManager) {
+        let remap = new.remap(old);
+        for x in &mut self.hotbar {
+            *x = remap[*x as usize];
         }
+        self.item_models = Self::item_models(&self.hotbar, new);
     }
 
     pub fn draw_hotbar(&mut self) {
-        self.item_models[self.selected_block as usize].render();
+        self.item_models[self.selected_block].render();
     }
 
     pub fn update(&mut self, input: &InputState, game: &mut Game) {
@@ -99,7 +117,8 @@ impl Player {
         //     self.pos.y -= 0.2;
         // }
 
-        let collisions = collisions::check_collisions(self, game);
+        let collisions =
+            collisions::check_collisions(&mut game.chunks, self.pos, Self::RADIUS, Self::HEIGHT);
         // println!("{:?}", collisions);
         self.velocity.y += Game::GRAVITY;
 
@@ -144,10 +163,8 @@ impl Player {
 
         // if on ground
         if let Some(x) = collisions.top {
-            if x.1 == self.pos.y {
-                if input.keys_down.contains(&VirtualKeyCode::Space) {
-                    self.velocity.y += Self::JUMP_AMOUNT;
-                }
+            if x.1 == self.pos.y && input.keys_down.contains(&VirtualKeyCode::Space) {
+                self.velocity.y += Self::JUMP_AMOUNT;
             }
         }
 
@@ -181,41 +198,60 @@ impl Player {
         if input.keys_pressed.contains(&VirtualKeyCode::Key8) {
             self.selected_block = 7;
         }
+        if input.keys_pressed.contains(&VirtualKeyCode::Key9) {
+            self.selected_block = 8;
+        }
+        // the scroll wheel goes through the hotbar
+        if input.pressed_buttons[3] {
+            self.selected_block = (self.selected_block + self.hotbar.len() - 1) % self.hotbar.len();
+        }
+        if input.pressed_buttons[4] {
+            self.selected_block = (self.selected_block + 1) % self.hotbar.len();
+        }
 
-        if input.keys_pressed.contains(&VirtualKeyCode::E) {
-            let pos = raycast::raycast(
-                &mut game.chunks,
-                &game.blocks,
-                self.pos + Vec3::new(0.0, Self::CAMERA_HEIGHT, 0.0),
-                front.transform_point3(Vec3::new(0.0, 0.0, 1.0)),
-            );
-            if let Some(res) = pos {
-                let bp =
-                    Into::<Vec3>::into(res.block + res.side.to_pos()) + Vec3::new(0.5, 0.5, 0.5);
-                if (self.pos.x - Self::RADIUS >= bp.x + 0.5
-                    || self.pos.x + Self::RADIUS <= bp.x - 0.5)
-                    || (self.pos.y >= bp.y + 0.5 || self.pos.y + Self::HEIGHT <= bp.y - 0.5)
-                    || (self.pos.z - Self::RADIUS >= bp.z + 0.5
-                        || self.pos.z + Self::RADIUS <= bp.z - 0.5)
-                {
-                    game.chunks.set_block(
-                        res.block + res.side.to_pos(),
-                        self.hotbar[self.selected_block],
-                    );
-                }
-            }
+        let look = front.transform_point3(Vec3::new(0.0, 0.0, 1.0));
+        // left click uses the block, or places one if it can't be used
+        if input.keys_pressed.contains(&VirtualKeyCode::E) || input.pressed_buttons[0] {
+            self.use_or_place(game, look);
         }
-        if input.keys_pressed.contains(&VirtualKeyCode::Q) {
+        if input.keys_pressed.contains(&VirtualKeyCode::Q) || input.pressed_buttons[2] {
             let pos = raycast::raycast(
                 &mut game.chunks,
-                &game.blocks,
                 self.pos + Vec3::new(0.0, Self::CAMERA_HEIGHT, 0.0),
-                front.transform_point3(Vec3::new(0.0, 0.0, 1.0)),
+                look,
+                self.reach,
             );
             if let Some(res) = pos {
-                game.chunks
-                    .set_block(res.block, game.blocks[String::from("air")]);
+                game.chunks.break_block(res.block);
             }
         }
     }
+
+    /// Uses the block the player is looking at, or places the selected block next to it
+    fn use_or_place(&mut self, game: &mut Game, look: Vec3) {
+        let res = match raycast::raycast(
+            &mut game.chunks,
+            self.pos + Vec3::new(0.0, Self::CAMERA_HEIGHT, 0.0),
+            look,
+            self.reach,
+        ) {
+            Some(x) => x,
+            None => return,
+        };
+        if game.chunks.use_block(res.block) {
+            return;
+        }
+        let bp = Into::<Vec3>::into(res.block + res.side.to_pos()) + Vec3::new(0.5, 0.5, 0.5);
+        if (self.pos.x - Self::RADIUS >= bp.x + 0.5 || self.pos.x + Self::RADIUS <= bp.x - 0.5)
+            || (self.pos.y >= bp.y + 0.5 || self.pos.y + Self::HEIGHT <= bp.y - 0.5)
+            || (self.pos.z - Self::RADIUS >= bp.z + 0.5 || self.pos.z + Self::RADIUS <= bp.z - 0.5)
+        {
+            let block = self.hotbar[self.selected_block];
+            let state = game.blocks[block]
+                .states
+                .placement_state(-res.side, look, res.point);
+            game.chunks
+                .place_block(res.block + res.side.to_pos(), block, state);
+        }
+    }
 }
@@ -1,56 +1,279 @@
 use super::*;
+use block::{BlockID, BlockState, BlockType};
+use util::{BlockCollider, BlockSide};
 
 pub struct RaycastResult {
     pub point: Vec3,
     pub block: BlockPos,
-    pub side: util::BlockSide,
+    /// The side of the selection box which was hit
+    pub side: BlockSide,
+    /// The selection box which was hit, in world coordinates, e.g. for drawing its outline
+    #[allow(unused)]
+    pub hit_box: BlockCollider,
 }
 
-fn get_ray_side(point: Vec3, direction: Vec3) -> (util::BlockSide, f32) {
-    let candidates = [
-        (util::BlockSide::Left, -point.x / direction.x),
-        (util::BlockSide::Right, (1.0 - point.x) / direction.x),
-        (util::BlockSide::Bottom, -point.y / direction.y),
-        (util::BlockSide::Top, (1.0 - point.y) / direction.y),
-        (util::BlockSide::Back, -point.z / direction.z),
-        (util::BlockSide::Front, (1.0 - point.z) / direction.z),
+/// A block entered by a ray
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayStep {
+    pub pos: BlockPos,
+    /// The face of the block through which the ray entered it, None for the block it starts in
+    pub face: Option<BlockSide>,
+    /// How far along the ray the block was entered
+    pub distance: f32,
+}
+
+/// Walks along a ray block by block (a DDA), yielding every block it enters until it gets
+/// further than the maximum distance
+pub struct VoxelRay {
+    /// Normalized, so that the distances are in blocks
+    direction: Vec3,
+    max_distance: f32,
+    block: BlockPos,
+    /// The distance at which the ray crosses the next boundary on every axis
+    next_boundary: Vec3,
+    /// The distance between the boundaries on every axis
+    delta: Vec3,
+    started: bool,
+}
+
+impl VoxelRay {
+    pub fn new(origin: Vec3, direction: Vec3, max_distance: f32) -> Self {
+        let direction = direction.normalize_or_zero();
+        let block = BlockPos::new(
+            origin.x.floor() as _,
+            origin.y.floor() as _,
+            origin.z.floor() as _,
+        );
+        let start = Vec3::from(block);
+        let mut next_boundary = Vec3::splat(f32::INFINITY);
+        let mut delta = Vec3::splat(f32::INFINITY);
+        for i in 0..3 {
+            if direction[i] > 0.0 {
+                next_boundary[i] = (start[i] + 1.0 - origin[i]) / direction[i];
+            } else if direction[i] < 0.0 {
+                next_boundary[i] = (start[i] - origin[i]) / direction[i];
+            }
+            if direction[i] != 0.0 {
+                delta[i] = 1.0 / direction[i].abs();
+            }
+        }
+        Self {
+            direction,
+            max_distance,
+            block,
+            next_boundary,
+            delta,
+            started: false,
+        }
+    }
+}
+
+impl Iterator for VoxelRay {
+    type Item = RayStep;
+
+    fn next(&mut self) -> Option<RayStep> {
+        if !self.started {
+            self.started = true;
+            return Some(RayStep {
+                pos: self.block,
+                face: None,
+                distance: 0.0,
+            });
+        }
+        // cross the closest boundary
+        let axis = if self.next_boundary.x < self.next_boundary.y {
+            if self.next_boundary.x < self.next_boundary.z {
+                0
+            } else {
+                2
+            }
+        } else if self.next_boundary.y < self.next_boundary.z {
+            1
+        } else {
+            2
+        };
+        let distance = self.next_boundary[axis];
+        if distance > self.max_distance {
+            return None;
+        }
+        self.next_boundary[axis] += self.delta[axis];
+        // the side of the new block facing the one the ray comes from
+        let face = match (axis, self.direction[axis] > 0.0) {
+            (0, true) => BlockSide::Left,
+            (0, false) => BlockSide::Right,
+            (1, true) => BlockSide::Bottom,
+            (1, false) => BlockSide::Top,
+            (_, true) => BlockSide::Back,
+            (_, false) => BlockSide::Front,
+        };
+        self.block += -face;
+        Some(RayStep {
+            pos: self.block,
+            face: Some(face),
+            distance,
+        })
+    }
+}
+
+/// The blocks along a ray with their state, for which `filter` returns true (e.g. to skip water
+/// or plants, `|_, _| true` keeps all of them). Blocks which can't be loaded are skipped.
+pub fn traverse<'a>(
+    world: &'a mut ChunkServer,
+    origin: Vec3,
+    direction: Vec3,
+    max_distance: f32,
+    filter: impl Fn(BlockPos, &BlockType) -> bool + 'a,
+) -> impl Iterator<Item = (RayStep, BlockID, BlockState)> + 'a {
+    let blocks = world.block_manager.clone();
+    VoxelRay::new(origin, direction, max_distance).filter_map(move |step| {
+        let id = world.get_block(step.pos)?;
+        if !filter(step.pos, &blocks[id]) {
+            return None;
+        }
+        Some((step, id, world.get_state(step.pos).unwrap_or(0)))
+    })
+}
+
+/// Where a ray enters a box: the distance along the ray (in multiples of `direction`) and the
+/// side of the box. None if it misses the box.
+fn intersect_box(origin: Vec3, direction: Vec3, b: &BlockCollider) -> Option<(f32, BlockSide)> {
+    let min = Vec3::new(b.x, b.y, b.z);
+    let max = min + Vec3::new(b.w, b.h, b.d);
+    let axes = [
+        (BlockSide::Left, BlockSide::Right),
+        (BlockSide::Bottom, BlockSide::Top),
+        (BlockSide::Back, BlockSide::Front),
     ];
-    let mut smallest = (util::BlockSide::Bottom, std::f32::MAX);
-    for i in candidates {
-        if i.1 > 0.00 && i.1 < smallest.1 {
-            smallest = i;
+    let mut near = (f32::NEG_INFINITY, BlockSide::Bottom);
+    let mut far = f32::INFINITY;
+    for (i, (negative, positive)) in axes.into_iter().enumerate() {
+        if direction[i] == 0.0 {
+            if origin[i] < min[i] || origin[i] > max[i] {
+                return None;
+            }
+            continue;
+        }
+        let t1 = (min[i] - origin[i]) / direction[i];
+        let t2 = (max[i] - origin[i]) / direction[i];
+        // the ray enters through the side it reaches first
+        let enter = if t1 < t2 {
+            (t1, negative)
+        } else {
+            (t2, positive)
+        };
+        if enter.0 > near.0 {
+            near = enter;
         }
+        far = far.min(t1.max(t2));
     }
-    smallest
+    if near.0 > far || far < 0.0 {
+        return None;
+    }
+    Some((near.0.max(0.0), near.1))
 }
 
+/// Finds the first selectable block whose selection box the ray hits, at most `max_distance`
+/// blocks away
 pub fn raycast(
-    server: &mut ChunkServer,
-    block_manager: &BlockManager,
-    mut point: Vec3,
+    world: &mut ChunkServer,
+    origin: Vec3,
     direction: Vec3,
+    max_distance: f32,
 ) -> Option<RaycastResult> {
-    let mut block = BlockPos::new(
-        point.x.floor() as i32 - (point.x == point.x.floor() && direction.x < 0.0) as i32,
-        point.y.floor() as i32 - (point.y == point.y.floor() && direction.y < 0.0) as i32,
-        point.z.floor() as i32 - (point.z == point.z.floor() && direction.z < 0.0) as i32,
-    );
-    for _ in 0..20 {
-        // point += direction / 10.0;
-        let side = get_ray_side(
-            point - Vec3::new(block.x as _, block.y as _, block.z as _),
-            direction,
+    let blocks = world.block_manager.clone();
+    let direction = direction.normalize_or_zero();
+    let selectable = |_, b: &BlockType| b.selectable;
+    traverse(world, origin, direction, max_distance, selectable).find_map(|(step, id, state)| {
+        let pos = step.pos;
+        // the closest selection box of the block which the ray hits
+        let offset = Vec3::from(pos);
+        blocks[id]
+            .selection_boxes(state)
+            .into_iter()
+            .map(|x| BlockCollider {
+                x: x.x + offset.x,
+                y: x.y + offset.y,
+                z: x.z + offset.z,
+                ..x
+            })
+            .filter_map(|x| intersect_box(origin, direction, &x).map(|(t, side)| (t, side, x)))
+            .filter(|x| x.0 <= max_distance)
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(t, side, hit_box)| RaycastResult {
+                point: origin + direction * t,
+                block: pos,
+                side,
+                hit_box,
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blocks(ray: VoxelRay) -> Vec<BlockPos> {
+        ray.map(|x| x.pos).collect()
+    }
+
+    #[test]
+    fn voxel_ray_along_an_axis() {
+        let blocks = blocks(VoxelRay::new(Vec3::new(0.5, 0.5, 0.5), Vec3::X, 3.0));
+        let expected: Vec<BlockPos> = (0..4).map(|x| BlockPos::new(x, 0, 0)).collect();
+        assert_eq!(blocks, expected);
+    }
+
+    #[test]
+    fn voxel_ray_negative_direction() {
+        let blocks = blocks(VoxelRay::new(Vec3::new(0.5, -0.5, 0.5), -Vec3::Y, 2.0));
+        let expected: Vec<BlockPos> = (1..4).map(|y| BlockPos::new(0, -y, 0)).collect();
+        assert_eq!(blocks, expected);
+    }
+
+    #[test]
+    fn voxel_ray_diagonal_steps_one_axis_at_a_time() {
+        let blocks = blocks(VoxelRay::new(
+            Vec3::new(0.5, 0.2, 0.5),
+            Vec3::new(1.0, 1.0, 0.0),
+            4.0,
+        ));
+        for pair in blocks.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            assert_eq!((b.x - a.x).abs() + (b.y - a.y).abs() + (b.z - a.z).abs(), 1);
+        }
+        assert_eq!(blocks.last(), Some(&BlockPos::new(3, 3, 0)));
+    }
+
+    #[test]
+    fn voxel_ray_without_direction() {
+        let blocks = blocks(VoxelRay::new(Vec3::new(2.5, 1.0, -0.5), Vec3::ZERO, 5.0));
+        assert_eq!(blocks, [BlockPos::new(2, 1, -1)]);
+    }
+
+    #[test]
+    fn voxel_ray_faces_and_distances() {
+        let steps: Vec<RayStep> = VoxelRay::new(Vec3::new(0.5, 0.5, 0.5), -Vec3::Z, 1.0).collect();
+        assert_eq!(
+            steps,
+            [
+                RayStep {
+                    pos: BlockPos::new(0, 0, 0),
+                    face: None,
+                    distance: 0.0,
+                },
+                RayStep {
+                    pos: BlockPos::new(0, 0, -1),
+                    face: Some(BlockSide::Front),
+                    distance: 0.5,
+                },
+            ]
         );
-        point += direction * side.1;
-        block += side.0;
-
-        if block_manager[server.get_block(block)?].solid {
-            return Some(RaycastResult {
-                point,
-                block,
-                side: -side.0,
-            });
+        // the block is entered through the face pointing back at the previous one
+        let steps: Vec<RayStep> =
+            VoxelRay::new(Vec3::new(0.3, 0.6, 0.2), Vec3::new(-1.0, 2.0, 3.0), 6.0).collect();
+        for pair in steps.windows(2) {
+            assert_eq!(pair[1].pos + pair[1].face.unwrap(), pair[0].pos);
+            assert!(pair[1].distance >= pair[0].distance);
         }
     }
-    None
 }
@@ -1,5 +1,7 @@
+use super::block::RenderLayer;
 use crate::graphics::{shader, Shader};
 use glam::Mat4;
+use glam::Vec2;
 use glam::Vec3;
 
 pub struct RenderView {
@@ -7,6 +9,10 @@ pub struct RenderView {
     view_matrix: i32,
     model_matrix: i32,
     camera_position: i32,
+    hidden_min: i32,
+    hidden_max: i32,
+    dither_range: i32,
+    render_layer: i32,
 }
 
 impl RenderView {
@@ -14,12 +20,25 @@ impl RenderView {
         let view_matrix = shader.create_uniform("view");
         let model_matrix = shader.create_uniform("model");
         let camera_position = shader.create_uniform("camera_position");
-        Self {
+        let hidden_min = shader.create_uniform("hidden_min");
+        let hidden_max = shader.create_uniform("hidden_max");
+        let dither_range = shader.create_uniform("dither_range");
+        let render_layer = shader.create_uniform("render_layer");
+        // the hidden chunks are on the second texture unit
+        let hidden_chunks = shader.create_uniform("hidden_chunks");
+        shader.set_uniform(hidden_chunks, shader::Uniform::Int(1));
+        let mut view = Self {
             shader,
             view_matrix,
             model_matrix,
             camera_position,
-        }
+            hidden_min,
+            hidden_max,
+            dither_range,
+            render_layer,
+        };
+        view.set_dither_range(0.0, 1.0);
+        view
     }
 
     pub fn set_view(&mut self, matrix: Mat4) {
@@ -34,6 +53,30 @@ impl RenderView {
         self.shader
             .set_uniform(self.camera_position, shader::Uniform::Vec3(matrix));
     }
+    /// Nothing is drawn over the chunks inside of the box which are marked in the texture on the
+    /// second texture unit, one pixel per chunk column. An empty box hides nothing.
+    pub fn set_hidden_chunks(&mut self, min: Vec3, max: Vec3) {
+        self.shader
+            .set_uniform(self.hidden_min, shader::Uniform::Vec3(min));
+        self.shader
+            .set_uniform(self.hidden_max, shader::Uniform::Vec3(max));
+    }
+
+    /// Only draws the pixels whose threshold in a dither pattern is between `start` and `end`,
+    /// used to fade meshes in and out
+    pub fn set_dither_range(&mut self, start: f32, end: f32) {
+        self.shader.set_uniform(
+            self.dither_range,
+            shader::Uniform::Vec2(Vec2::new(start, end)),
+        );
+    }
+
+    /// Decides what happens to transparent pixels: they are opaque in the opaque layer, either
+    /// discarded or opaque in the cutout layer, and blended in the translucent layer
+    pub fn set_render_layer(&mut self, layer: RenderLayer) {
+        self.shader
+            .set_uniform(self.render_layer, shader::Uniform::Int(layer as i32));
+    }
 
     pub fn bind(&mut self) {
         self.shader.bind();
@@ -2,11 +2,51 @@
 out vec4 final_color;
 in vec2 frag_texcoords;
 in vec3 frag_normals;
+in vec3 frag_color;
+flat in vec4 frag_tile;
+in float frag_ao;
+in vec3 frag_world_pos;
 uniform sampler2D textur;
+// the far terrain isn't drawn over the chunks inside of the box which are marked in
+// hidden_chunks, it has one pixel for every chunk column
+uniform vec3 hidden_min;
+uniform vec3 hidden_max;
+uniform sampler2D hidden_chunks;
+// only pixels whose threshold is in the range are drawn, to fade the far terrain in and out
+uniform vec2 dither_range;
+// 0 for opaque, 1 for cutout and 2 for translucent meshes
+uniform int render_layer;
 void main() {
+    if (all(greaterThanEqual(frag_world_pos, hidden_min)) && all(lessThan(frag_world_pos, hidden_max))) {
+        ivec2 column = ivec2(floor((frag_world_pos.xz - hidden_min.xz) / 16.0));
+        if (texelFetch(hidden_chunks, column, 0).x > 0.5) {
+            discard;
+        }
+    }
+    // a 4x4 Bayer matrix
+    const float bayer[16] = float[16](0.0, 8.0, 2.0, 10.0, 12.0, 4.0, 14.0, 6.0, 3.0, 11.0, 1.0, 9.0, 15.0, 7.0, 13.0, 5.0);
+    ivec2 pixel = ivec2(gl_FragCoord.xy) % 4;
+    float threshold = (bayer[pixel.y * 4 + pixel.x] + 0.5) / 16.0;
+    if (threshold < dither_range.x || threshold >= dither_range.y) {
+        discard;
+    }
     // final_color = texture(textur, frag_texcoords);
     float light = dot(frag_normals, normalize(vec3(1.0, 2.0, -1.0)));
-    light = light / 4.0 + 0.75;
-    final_color = texture(textur, frag_texcoords) * light;
-    final_color.w = 1.0;
+    light = (light / 4.0 + 0.75) * frag_ao;
+    vec2 texcoords = frag_texcoords;
+    // merged faces repeat the texture, their texture coordinates are in units of 16 tiles. The
+    // mip level is chosen from the coordinates before they wrap around, otherwise it jumps at the
+    // edges of the tiles.
+    vec2 repeats = frag_texcoords * 16.0;
+    vec2 unwrapped = frag_tile.x != frag_tile.z ? repeats * (frag_tile.zw - frag_tile.xy) : texcoords;
+    if (frag_tile.x != frag_tile.z) {
+        texcoords = mix(frag_tile.xy, frag_tile.zw, fract(repeats));
+    }
+    final_color = textureGrad(textur, texcoords, dFdx(unwrapped), dFdy(unwrapped)) * vec4(frag_color * light, 1.0);
+    if (render_layer == 1 && final_color.w < 0.5) {
+        discard;
+    }
+    if (render_layer != 2) {
+        final_color.w = 1.0;
+    }
 }
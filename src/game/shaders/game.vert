@@ -2,13 +2,37 @@
 layout (location = 0) in vec3 pos;
 layout (location = 1) in vec2 texCoords;
 layout (location = 2) in vec3 normals;
+layout (location = 3) in vec3 color;
+layout (location = 4) in vec4 tile;
+layout (location = 5) in float ao;
+// 1 + the index of the face in FACE_NORMALS, 0 if the normal is in `normals`. Models don't have
+// it, so it's 0 for them.
+layout (location = 6) in float face;
 uniform mat4 view;
 uniform mat4 model;
 uniform vec3 camera_position;
 out vec2 frag_texcoords;
+out vec3 frag_color;
+flat out vec4 frag_tile;
+out float frag_ao;
 out vec3 frag_normals;
+out vec3 frag_world_pos;
+const vec3 FACE_NORMALS[6] = vec3[6](
+    vec3(0.0, 1.0, 0.0),
+    vec3(0.0, -1.0, 0.0),
+    vec3(-1.0, 0.0, 0.0),
+    vec3(1.0, 0.0, 0.0),
+    vec3(0.0, 0.0, 1.0),
+    vec3(0.0, 0.0, -1.0)
+);
 void main() {
     frag_texcoords = texCoords;
-    frag_normals = normalize(normals);
-    gl_Position = view * model * vec4(pos - camera_position, 1.0);
+    frag_color = color;
+    frag_tile = tile;
+    frag_ao = ao;
+    int face_index = int(face + 0.5) & 7;
+    frag_normals = face_index > 0 ? FACE_NORMALS[face_index - 1] : normalize(normals);
+    vec4 world_pos = model * vec4(pos, 1.0);
+    frag_world_pos = world_pos.xyz;
+    gl_Position = view * vec4(world_pos.xyz - camera_position, 1.0);
 }
@@ -1,13 +1,26 @@
 use super::*;
 
-pub fn ui_shader() -> Shader {
+/// Reads a shader from the last directory which has it, or uses the one built into the game if
+/// none of them do
+fn load(dirs: &[String], name: &str, builtin: &[u8]) -> Vec<u8> {
+    dirs.iter()
+        .rev()
+        .find_map(|x| std::fs::read(format!("{}/{}", x, name)).ok())
+        .unwrap_or_else(|| builtin.to_vec())
+}
+
+pub fn ui_shader(dirs: &[String]) -> Result<Shader, String> {
     const VSCODE: &[u8] = include_bytes!("ui.vert");
     const FSCODE: &[u8] = include_bytes!("ui.frag");
-    unsafe { Shader::new(VSCODE, FSCODE) }
+    let vscode = load(dirs, "ui.vert", VSCODE);
+    let fscode = load(dirs, "ui.frag", FSCODE);
+    unsafe { Shader::new(&vscode, &fscode) }
 }
 
-pub fn game_shader() -> Shader {
+pub fn game_shader(dirs: &[String]) -> Result<Shader, String> {
     const VSCODE: &[u8] = include_bytes!("game.vert");
     const FSCODE: &[u8] = include_bytes!("game.frag");
-    unsafe { Shader::new(VSCODE, FSCODE) }
+    let vscode = load(dirs, "game.vert", VSCODE);
+    let fscode = load(dirs, "game.frag", FSCODE);
+    unsafe { Shader::new(&vscode, &fscode) }
 }
@@ -1,8 +1,9 @@
 #version 330 core
 out vec4 final_color;
 in vec2 frag_texcoords;
+in vec3 frag_color;
 uniform sampler2D textur;
 void main() {
-    final_color = texture(textur, frag_texcoords);
+    final_color = texture(textur, frag_texcoords) * vec4(frag_color, 1.0);
     final_color.w = 1.0;
 }
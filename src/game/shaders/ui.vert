@@ -3,10 +3,13 @@ layout (location = 0) in vec3 pos;
 layout (location = 1) in vec2 texCoords;
 // we technically don't need normals but I want this to be compatible with models
 layout (location = 2) in vec3 normals;
+layout (location = 3) in vec3 color;
 uniform mat4 view;
 uniform mat4 model;
 out vec2 frag_texcoords;
+out vec3 frag_color;
 void main() {
     frag_texcoords = texCoords;
+    frag_color = color;
     gl_Position = view * model * vec4(pos, 1.0);
 }
@@ -8,7 +8,7 @@ pub struct BlockPos {
 }
 
 #[allow(unused)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct BlockSides<T> {
     pub top: T,
     pub bottom: T,
@@ -39,7 +39,7 @@ pub struct BlockCollider {
     pub d: f32,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct TexRect {
     pub left: f32,
     pub top: f32,
@@ -63,6 +63,49 @@ where
     }
 }
 
+impl<T> BlockSides<T> {
+    /// Creates the sides by calling `f` for each one of them
+    pub fn from_fn(f: impl Fn(BlockSide) -> T) -> Self {
+        Self {
+            top: f(BlockSide::Top),
+            bottom: f(BlockSide::Bottom),
+            left: f(BlockSide::Left),
+            right: f(BlockSide::Right),
+            front: f(BlockSide::Front),
+            back: f(BlockSide::Back),
+        }
+    }
+
+    pub fn get(&self, side: BlockSide) -> &T {
+        match side {
+            BlockSide::Top => &self.top,
+            BlockSide::Bottom => &self.bottom,
+            BlockSide::Left => &self.left,
+            BlockSide::Right => &self.right,
+            BlockSide::Front => &self.front,
+            BlockSide::Back => &self.back,
+        }
+    }
+}
+
+impl BlockCollider {
+    /// Transforms the collider by a matrix, the result is the bounding box of the transformed box
+    pub fn transform(&self, mat: Mat4) -> Self {
+        let a = mat.transform_point3(Vec3::new(self.x, self.y, self.z));
+        let b = mat.transform_point3(Vec3::new(self.x + self.w, self.y + self.h, self.z + self.d));
+        let min = a.min(b);
+        let max = a.max(b);
+        Self {
+            x: min.x,
+            y: min.y,
+            z: min.z,
+            w: max.x - min.x,
+            h: max.y - min.y,
+            d: max.z - min.z,
+        }
+    }
+}
+
 impl BlockPos {
     #[inline]
     pub fn new(x: i32, y: i32, z: i32) -> Self {
@@ -108,15 +151,62 @@ impl std::ops::Add<BlockPos> for BlockPos {
     }
 }
 
-impl Into<glam::Vec3> for BlockPos {
-    fn into(self) -> glam::Vec3 {
-        glam::Vec3::new(self.x as _, self.y as _, self.z as _)
+impl From<BlockPos> for glam::Vec3 {
+    fn from(pos: BlockPos) -> glam::Vec3 {
+        glam::Vec3::new(pos.x as _, pos.y as _, pos.z as _)
     }
 }
 
 impl BlockSide {
+    pub const ALL: [BlockSide; 6] = [
+        BlockSide::Top,
+        BlockSide::Bottom,
+        BlockSide::Left,
+        BlockSide::Right,
+        BlockSide::Front,
+        BlockSide::Back,
+    ];
+
+    /// Name of the side, as used in blocks.json
+    pub fn name(self) -> &'static str {
+        match self {
+            BlockSide::Top => "top",
+            BlockSide::Bottom => "bottom",
+            BlockSide::Left => "left",
+            BlockSide::Right => "right",
+            BlockSide::Front => "front",
+            BlockSide::Back => "back",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|x| x.name() == name)
+    }
+
+    /// The side which a vector is pointing to the most
+    pub fn from_vec(v: Vec3) -> Self {
+        let a = v.abs();
+        if a.x >= a.y && a.x >= a.z {
+            if v.x > 0.0 {
+                BlockSide::Right
+            } else {
+                BlockSide::Left
+            }
+        } else if a.y >= a.z {
+            if v.y > 0.0 {
+                BlockSide::Top
+            } else {
+                BlockSide::Bottom
+            }
+        } else if v.z > 0.0 {
+            BlockSide::Front
+        } else {
+            BlockSide::Back
+        }
+    }
+
     #[inline]
-    pub fn to_pos(&self) -> BlockPos {
+    pub fn to_pos(self) -> BlockPos {
         match self {
             BlockSide::Top => BlockPos::new(0, 1, 0),
             BlockSide::Bottom => BlockPos::new(0, -1, 0),
@@ -142,3 +232,95 @@ impl std::ops::Neg for BlockSide {
         }
     }
 }
+
+/// The part of the world which the camera can see
+pub struct Frustum {
+    /// `(normal, distance)` of the six planes, relative to the camera, pointing inwards
+    planes: [Vec4; 6],
+    camera: Vec3,
+}
+
+impl Frustum {
+    /// `view` is the view and projection matrix, which is relative to the camera
+    pub fn new(view: Mat4, camera: Vec3) -> Self {
+        let (x, y, z, w) = (view.row(0), view.row(1), view.row(2), view.row(3));
+        Self {
+            planes: [w + x, w - x, w + y, w - y, w + z, w - z],
+            camera,
+        }
+    }
+
+    pub fn camera(&self) -> Vec3 {
+        self.camera
+    }
+
+    /// Whether any part of a box could be visible, it may be behind something though
+    pub fn contains_box(&self, min: Vec3, max: Vec3) -> bool {
+        let (min, max) = (min - self.camera, max - self.camera);
+        self.planes.iter().all(|plane| {
+            // the corner furthest along the normal
+            let normal = plane.truncate();
+            let corner = Vec3::select(normal.cmpge(Vec3::ZERO), max, min);
+            normal.dot(corner) + plane.w >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Looking along +z from `camera`, like the player without any rotation
+    fn frustum(camera: Vec3) -> Frustum {
+        Frustum::new(Mat4::perspective_lh(1.0, 1.0, 0.1, 100.0), camera)
+    }
+
+    /// Whether the box with the corners at these offsets from the camera is in the frustum
+    fn contains(frustum: &Frustum, min: Vec3, max: Vec3) -> bool {
+        frustum.contains_box(frustum.camera() + min, frustum.camera() + max)
+    }
+
+    #[test]
+    fn frustum_rejects_boxes_behind_the_camera() {
+        let f = frustum(Vec3::new(10.0, 5.0, -20.0));
+        assert!(!contains(
+            &f,
+            Vec3::new(-1.0, -1.0, -6.0),
+            Vec3::new(1.0, 1.0, -4.0)
+        ));
+        assert!(contains(
+            &f,
+            Vec3::new(-1.0, -1.0, 4.0),
+            Vec3::new(1.0, 1.0, 6.0)
+        ));
+    }
+
+    #[test]
+    fn frustum_keeps_boxes_on_a_plane() {
+        let f = frustum(Vec3::new(10.0, 5.0, -20.0));
+        // the left plane is about 5.5 blocks from the middle at a distance of 10
+        assert!(contains(
+            &f,
+            Vec3::new(-20.0, -1.0, 9.0),
+            Vec3::new(0.0, 1.0, 11.0)
+        ));
+        assert!(!contains(
+            &f,
+            Vec3::new(-30.0, -1.0, 9.0),
+            Vec3::new(-20.0, 1.0, 11.0)
+        ));
+        // the camera's own chunk is cut by the near plane
+        assert!(contains(&f, Vec3::splat(-8.0), Vec3::splat(8.0)));
+        // and the far plane
+        assert!(contains(
+            &f,
+            Vec3::new(-1.0, -1.0, 90.0),
+            Vec3::new(1.0, 1.0, 110.0)
+        ));
+        assert!(!contains(
+            &f,
+            Vec3::new(-1.0, -1.0, 110.0),
+            Vec3::new(1.0, 1.0, 120.0)
+        ));
+    }
+}
@@ -0,0 +1,45 @@
+use super::*;
+
+/// How warm the climate is at a given position, from 0 to 1
+pub fn temperature(x: i32, z: i32) -> f32 {
+    let (x, z) = (x as f32, z as f32);
+    let t = (x / 211.0 + (z / 157.0).sin()).sin() * 0.6 + (z / 97.0 - x / 331.0).cos() * 0.4;
+    t * 0.5 + 0.5
+}
+
+/// How much it rains at a given position, from 0 to 1
+pub fn humidity(x: i32, z: i32) -> f32 {
+    let (x, z) = (x as f32, z as f32);
+    let h = (z / 193.0 + (x / 139.0).cos()).sin() * 0.6 + (x / 113.0 + z / 277.0).sin() * 0.4;
+    h * 0.5 + 0.5
+}
+
+/// Mixes the colors of a cold, a dry and a lush climate
+fn climate_color(x: i32, z: i32, cold: Vec3, dry: Vec3, lush: Vec3) -> Vec3 {
+    let t = temperature(x, z);
+    let h = humidity(x, z);
+    let warm = dry.lerp(lush, h);
+    cold.lerp(warm, t)
+}
+
+/// Color of grass at a given position
+pub fn grass_color(x: i32, z: i32) -> Vec3 {
+    climate_color(
+        x,
+        z,
+        Vec3::new(0.50, 0.71, 0.59),
+        Vec3::new(0.75, 0.72, 0.36),
+        Vec3::new(0.36, 0.75, 0.24),
+    )
+}
+
+/// Color of leaves at a given position
+pub fn foliage_color(x: i32, z: i32) -> Vec3 {
+    climate_color(
+        x,
+        z,
+        Vec3::new(0.38, 0.60, 0.38),
+        Vec3::new(0.68, 0.64, 0.24),
+        Vec3::new(0.22, 0.62, 0.12),
+    )
+}
@@ -1,15 +1,76 @@
 use super::*;
+use block::{append_greedy, face_corners, quad_indices, GreedyFaces, RenderLayer, StateLayout};
 use meshdata::MeshData;
 use std::fs::File;
 use std::io::BufReader;
 use std::io::BufWriter;
 use std::io::Read;
 use std::io::Write;
+use util::BlockSide;
+
+/// Brightness of a vertex with 0 to 3 free blocks around it, see `Chunk::vertex_ao`
+const AO_CURVE: [f32; 4] = [0.45, 0.65, 0.82, 1.0];
+
+/// The meshes of a chunk, one for every render layer
+pub struct ChunkMesh {
+    pub opaque: ArenaMesh,
+    pub cutout: ArenaMesh,
+    pub translucent: ArenaMesh,
+    /// The translucent faces, kept so they can be sorted again while the camera is in the chunk.
+    /// None if there aren't any.
+    translucent_data: Option<MeshData>,
+    /// The block with the camera which the translucent faces were last sorted for
+    sorted_for: Option<BlockPos>,
+}
+
+impl ChunkMesh {
+    pub fn layer(&self, layer: RenderLayer) -> &ArenaMesh {
+        match layer {
+            RenderLayer::Opaque => &self.opaque,
+            RenderLayer::Cutout => &self.cutout,
+            RenderLayer::Translucent => &self.translucent,
+        }
+    }
+
+    /// Whether there is nothing to draw
+    pub fn is_empty(&self) -> bool {
+        self.opaque.is_empty() && self.cutout.is_empty() && self.translucent.is_empty()
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.opaque.vertex_count() + self.cutout.vertex_count() + self.translucent.vertex_count()
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        let data = self
+            .translucent_data
+            .as_ref()
+            .map_or(0, |x| x.memory_usage());
+        self.opaque.memory_usage()
+            + self.cutout.memory_usage()
+            + self.translucent.memory_usage()
+            + data
+    }
+}
 
 pub struct Chunk {
     pub pos: BlockPos,
-    pub model: Option<Model>,
+    /// None until the chunk has been meshed
+    pub mesh: Option<ChunkMesh>,
+    /// Which sides can be seen from each other, found together with the mesh
+    pub connections: Connections,
+    /// The level of detail which the mesh was made with, see `MeshDetail::level`
+    pub detail_level: u32,
     blocks: [[[BlockID; 16]; 16]; 16],
+    states: [[[BlockState; 16]; 16]; 16],
+    /// Positions inside the chunk which will be ticked, and in how many ticks
+    scheduled_ticks: Vec<(BlockPos, u32)>,
+    /// The block types which the IDs belong to, their names and states are saved with the chunk
+    /// so it can be loaded after the block types change
+    block_types: Arc<BlockManager>,
+
+    /// The frame in which the chunk was last accessed, used for evicting old chunks
+    pub last_used: u64,
 
     filename: String,
     save_dir: String,
@@ -21,28 +82,106 @@ unsafe impl Send for Chunk {}
 impl Drop for Chunk {
     fn drop(&mut self) {
         if self.modified {
-            self.save().unwrap();
+            if let Err(e) = self.save() {
+                println!("cannot save chunk {:?}: {}", self.pos, e);
+            }
+        }
+    }
+}
+
+/// How far the `terrain_height` can be above or below 0, the sum of the amplitudes of its waves
+pub const TERRAIN_AMPLITUDE: i32 = 1 + 2 + 1 + 1 + 6;
+
+/// The height of the highest block of the generated terrain
+pub fn terrain_height(x: i32, z: i32) -> i32 {
+    let (x, z) = (x as f32, z as f32);
+    let h = ((x / 2.0) - (z / 4.0)).sin() - 2.0 * ((x / 3.0) + (z / 80.0)).sin() + (z / 3.0).sin()
+        - ((z / 2.0) + (x / 4.0) + 1.0).sin()
+        + 6.0 * ((x / 12.0).sin() + (z / 9.0).cos()).sin();
+    h.floor() as i32
+}
+
+/// The generated block at a height relative to the `terrain_height`
+pub fn terrain_block(height: i32) -> &'static str {
+    if height > 0 {
+        "air"
+    } else if height == 0 {
+        "grass"
+    } else if height > -5 {
+        "dirt"
+    } else {
+        "stone"
+    }
+}
+
+/// The `Surface` of the blocks of a chunk, which `block` returns by their position in the chunk
+fn surface(manager: &BlockManager, block: impl Fn(usize, usize, usize) -> BlockID) -> Surface {
+    let mut surface = [[None; 8]; 8];
+    for (i, row) in surface.iter_mut().enumerate() {
+        for (j, sample) in row.iter_mut().enumerate() {
+            *sample = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                .iter()
+                .filter_map(|(a, b)| {
+                    (0..16).rev().find_map(|y| {
+                        let id = block(i * 2 + a, y, j * 2 + b);
+                        manager[id].cube.as_ref().map(|_| (y as u8, id))
+                    })
+                })
+                .max_by_key(|x| x.0);
         }
     }
+    surface
 }
 
 impl Chunk {
     /// Creates a new chunk, without a mesh
-    pub fn new(pos: BlockPos, manager: &BlockManager, save_dir: &str) -> Self {
+    pub fn new(pos: BlockPos, manager: &Arc<BlockManager>, save_dir: &str) -> Self {
         let mut chunk = Chunk {
             pos,
-            model: None,
+            mesh: None,
+            connections: Connections::ALL,
+            detail_level: 0,
             blocks: [[[0; 16]; 16]; 16],
-            filename: format!("{}/{}-{}-{}.chunk", save_dir, pos.x, pos.y, pos.z),
+            states: [[[0; 16]; 16]; 16],
+            scheduled_ticks: Vec::new(),
+            block_types: manager.clone(),
+            last_used: 0,
+            filename: Self::filename(pos, save_dir),
             modified: false,
             save_dir: String::from(save_dir),
         };
-        if let Err(_) = chunk.load() {
+        if let Err(e) = chunk.load() {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                println!("cannot load chunk {:?}: {}", pos, e);
+            }
+            // a broken file might have been read partly
+            chunk.states = [[[0; 16]; 16]; 16];
+            chunk.scheduled_ticks.clear();
             chunk.generate(manager);
         }
         chunk
     }
 
+    fn filename(pos: BlockPos, save_dir: &str) -> String {
+        format!("{}/{}-{}-{}.chunk", save_dir, pos.x, pos.y, pos.z)
+    }
+
+    /// The surface of a chunk, without keeping the chunk around. Chunks which haven't been saved
+    /// are only generated as far as needed, saved ones aren't saved again when their block types
+    /// are converted.
+    pub fn read_surface(pos: BlockPos, manager: &Arc<BlockManager>, save_dir: &str) -> Surface {
+        if !std::path::Path::new(&Self::filename(pos, save_dir)).exists() {
+            let ids = Self::terrain_ids(manager);
+            let heights = Self::terrain_heights(pos);
+            return surface(manager, |x, y, z| {
+                ids[(pos.y * 16 + y as i32 - heights[x][z]).clamp(-5, 1) as usize + 5]
+            });
+        }
+        let mut chunk = Chunk::new(pos, manager, save_dir);
+        chunk.modified = false;
+        chunk.surface(manager)
+    }
+
     pub fn load(&mut self) -> Result<(), std::io::Error> {
         let file = File::open(&self.filename)?;
         let mut file = BufReader::new(file);
@@ -50,16 +189,121 @@ impl Chunk {
             for j in 0..16 {
                 for k in 0..16 {
                     let mut buf: [u8; 1] = [0];
-                    file.read(&mut buf)?;
+                    file.read_exact(&mut buf)?;
                     self.blocks[i][j][k] = buf[0] as BlockID;
                 }
             }
         }
+        // chunks saved by older versions don't have the block states
+        for i in 0..16 {
+            for j in 0..16 {
+                for k in 0..16 {
+                    let mut buf: [u8; 1] = [0];
+                    match file.read_exact(&mut buf) {
+                        Ok(()) => self.states[i][j][k] = buf[0],
+                        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+                        Err(e) => return Err(e),
+                    }
+                }
+            }
+        }
+        // neither do they have the scheduled ticks
+        let mut count = [0; 4];
+        match file.read_exact(&mut count) {
+            Ok(()) => (),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        }
+        for _ in 0..u32::from_le_bytes(count) {
+            let mut buf = [0; 7];
+            file.read_exact(&mut buf)?;
+            let pos = BlockPos::new(buf[0] as _, buf[1] as _, buf[2] as _);
+            let delay = u32::from_le_bytes([buf[3], buf[4], buf[5], buf[6]]);
+            if pos.is_in_chunk() {
+                self.scheduled_ticks.push((pos, delay));
+            }
+        }
+        self.load_palette(&mut file)
+    }
+
+    /// Reads the block types which the chunk was saved with and converts the blocks and their
+    /// states to the current block types by name
+    ///
+    /// Chunks saved by older versions don't have a palette, their IDs are used as they are.
+    fn load_palette(&mut self, file: &mut impl Read) -> Result<(), std::io::Error> {
+        let mut count = [0; 2];
+        match file.read_exact(&mut count) {
+            Ok(()) => (),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        }
+        // blocks which aren't in the palette become air
+        let mut palette: Vec<Option<(String, StateLayout)>> = (0..256).map(|_| None).collect();
+        for _ in 0..u16::from_le_bytes(count) {
+            let id = read_u8(file)?;
+            let name = read_str(file)?;
+            let mut layout = StateLayout::default();
+            for _ in 0..read_u8(file)? {
+                let property = read_str(file)?;
+                let values = (0..read_u8(file)?)
+                    .map(|_| read_str(file))
+                    .collect::<Result<_, _>>()?;
+                layout.add(&property, values).ok_or_else(|| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidData, "too many states")
+                })?;
+            }
+            palette[id as usize] = Some((name, layout));
+        }
+        let types = self.block_types.clone();
+        self.remap(|id, state| match &palette[id as usize] {
+            Some((name, layout)) => {
+                let new = types.get(name).unwrap_or(0);
+                (new, layout.convert(state, &types[new].states))
+            }
+            None => (0, 0),
+        });
         Ok(())
     }
 
+    /// Writes the name and the state properties of every block type in the chunk
+    fn save_palette(&self, file: &mut impl Write) -> Result<(), std::io::Error> {
+        let mut ids: Vec<BlockID> = self.blocks.iter().flatten().flatten().copied().collect();
+        ids.sort_unstable();
+        ids.dedup();
+        ids.retain(|&x| (x as usize) < self.block_types.count());
+        file.write_all(&(ids.len() as u16).to_le_bytes())?;
+        for id in ids {
+            let t = &self.block_types[id];
+            file.write_all(&[id as u8])?;
+            write_str(file, &t.name)?;
+            file.write_all(&[t.states.properties.len() as u8])?;
+            for p in &t.states.properties {
+                write_str(file, &p.name)?;
+                file.write_all(&[p.values.len() as u8])?;
+                for v in &p.values {
+                    write_str(file, v)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the chunk has been changed since it was last saved
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    /// Approximate amount of memory used by the chunk and its mesh, in bytes
+    pub fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.filename.capacity()
+            + self.save_dir.capacity()
+            + self.scheduled_ticks.capacity() * std::mem::size_of::<(BlockPos, u32)>()
+            + self.mesh.as_ref().map_or(0, |x| x.memory_usage())
+    }
+
     pub fn save(&mut self) -> Result<(), std::io::Error> {
-        if let Ok(_) = std::fs::create_dir(self.save_dir.as_str()) {
+        if std::fs::create_dir(self.save_dir.as_str()).is_ok() {
             println!("creating a new save!");
         }
         let file = File::create(&self.filename)?;
@@ -68,47 +312,59 @@ impl Chunk {
             for j in 0..16 {
                 for k in 0..16 {
                     let x = [self.blocks[i][j][k] as u8];
-                    file.write(&x)?;
+                    file.write_all(&x)?;
                 }
             }
         }
+        for i in 0..16 {
+            for j in 0..16 {
+                for k in 0..16 {
+                    file.write_all(&[self.states[i][j][k]])?;
+                }
+            }
+        }
+        file.write_all(&(self.scheduled_ticks.len() as u32).to_le_bytes())?;
+        for (pos, delay) in &self.scheduled_ticks {
+            file.write_all(&[pos.x as u8, pos.y as u8, pos.z as u8])?;
+            file.write_all(&delay.to_le_bytes())?;
+        }
+        self.save_palette(&mut file)?;
+        file.flush()?;
+        self.modified = false;
         Ok(())
     }
 
+    /// The generated blocks for the heights from -5 to 1 relative to the `terrain_height`, every
+    /// height below and above has the same block as those
+    fn terrain_ids(manager: &BlockManager) -> [BlockID; 7] {
+        std::array::from_fn(|i| manager[String::from(terrain_block(i as i32 - 5))])
+    }
+
+    /// The `terrain_height` of every column of a chunk
+    fn terrain_heights(pos: BlockPos) -> [[i32; 16]; 16] {
+        std::array::from_fn(|x| {
+            std::array::from_fn(|z| terrain_height(pos.x * 16 + x as i32, pos.z * 16 + z as i32))
+        })
+    }
+
     pub fn generate(&mut self, manager: &BlockManager) {
-        let px = self.pos.x * 16;
-        let py = self.pos.y * 16;
-        let pz = self.pos.z * 16;
-        for i in 0..16 {
-            for j in 0..16 {
+        let ids = Self::terrain_ids(manager);
+        let heights = Self::terrain_heights(self.pos);
+        for (i, row) in heights.iter().enumerate() {
+            for (j, height) in row.iter().enumerate() {
                 for k in 0..16 {
-                    let x: i32 = px + i;
-                    let y: i32 = py + k;
-                    let z: i32 = pz + j;
-                    let h = ((x as f32 / 2.0) - (z as f32 / 4.0)).sin()
-                        - 2.0 * ((x as f32 / 3.0) + (z as f32 / 80.0)).sin()
-                        + (z as f32 / 3.0).sin()
-                        - ((z as f32 / 2.0) + (x as f32 / 4.0) + 1.0).sin()
-                        + 6.0 * ((x as f32 / 12.0).sin() + (z as f32 / 9.0).cos()).sin();
-                    let hd = y - (h.floor() as i32);
-                    if hd > 0 {
-                        self.blocks[i as usize][k as usize][j as usize] =
-                            manager[String::from("air")];
-                    } else if hd == 0 {
-                        self.blocks[i as usize][k as usize][j as usize] =
-                            manager[String::from("grass")];
-                    } else if hd > -5 {
-                        self.blocks[i as usize][k as usize][j as usize] =
-                            manager[String::from("dirt")];
-                    } else {
-                        self.blocks[i as usize][k as usize][j as usize] =
-                            manager[String::from("stone")];
-                    }
+                    let hd = (self.pos.y * 16 + k as i32 - height).clamp(-5, 1);
+                    self.blocks[i][k][j] = ids[(hd + 5) as usize];
                 }
             }
         }
     }
 
+    /// The highest full cube of every 2x2 columns, see `Surface`
+    pub fn surface(&self, manager: &BlockManager) -> Surface {
+        surface(manager, |x, y, z| self.blocks[x][y][z])
+    }
+
     /// Get a block, None if outside of chunk
     pub fn get_block(&self, pos: BlockPos) -> Option<BlockID> {
         if pos.is_in_chunk() {
@@ -118,16 +374,212 @@ impl Chunk {
         }
     }
 
-    /// Set a block, panics if outside a chunk
-    pub fn set_block(&mut self, pos: BlockPos, b: BlockID) {
+    /// Get the state of a block, None if outside of chunk
+    pub fn get_state(&self, pos: BlockPos) -> Option<BlockState> {
+        if pos.is_in_chunk() {
+            Some(self.states[pos.x as usize][pos.y as usize][pos.z as usize])
+        } else {
+            None
+        }
+    }
+
+    /// Set a block and its state, panics if outside a chunk
+    pub fn set_block(&mut self, pos: BlockPos, b: BlockID, state: BlockState) {
         self.modified = true;
         self.blocks[pos.x as usize][pos.y as usize][pos.z as usize] = b;
+        self.states[pos.x as usize][pos.y as usize][pos.z as usize] = state;
+    }
+
+    /// Switches to new block types, the blocks and their states are matched to the new types by
+    /// name
+    pub fn remap_blocks(&mut self, block_types: &Arc<BlockManager>) {
+        let remap = block_types.remap(&self.block_types);
+        let old = std::mem::replace(&mut self.block_types, block_types.clone());
+        self.remap(|id, state| match remap.get(id as usize) {
+            Some(&new) => (new, old[id].states.convert(state, &block_types[new].states)),
+            None => (0, 0),
+        });
+    }
+
+    /// Replaces every block and its state with `f(block, state)`
+    ///
+    /// The chunk is saved with the new blocks if any of them changed
+    fn remap(&mut self, f: impl Fn(BlockID, BlockState) -> (BlockID, BlockState)) {
+        let blocks = self.blocks.iter_mut().flatten().flatten();
+        let states = self.states.iter_mut().flatten().flatten();
+        for (b, s) in blocks.zip(states) {
+            let new = f(*b, *s);
+            if new != (*b, *s) {
+                (*b, *s) = new;
+                self.modified = true;
+            }
+        }
+    }
+
+    /// Ticks the block at a position inside the chunk after `delay` ticks, unless it is already
+    /// scheduled to be ticked sooner
+    pub fn schedule_tick(&mut self, pos: BlockPos, delay: u32) {
+        match self.scheduled_ticks.iter_mut().find(|x| x.0 == pos) {
+            Some(x) => x.1 = x.1.min(delay),
+            None => self.scheduled_ticks.push((pos, delay)),
+        }
+        self.modified = true;
+    }
+
+    /// Counts down the scheduled ticks, returns the positions which should be ticked now
+    pub fn take_due_ticks(&mut self) -> Vec<BlockPos> {
+        if self.scheduled_ticks.is_empty() {
+            return Vec::new();
+        }
+        // the remaining delays are saved with the chunk
+        self.modified = true;
+        let mut due = Vec::new();
+        self.scheduled_ticks.retain_mut(|(pos, delay)| {
+            if *delay <= 1 {
+                due.push(*pos);
+                false
+            } else {
+                *delay -= 1;
+                true
+            }
+        });
+        due
+    }
+
+    /// Number of vertices in the mesh
+    pub fn vertex_count(&self) -> usize {
+        self.mesh.as_ref().map_or(0, |x| x.vertex_count())
+    }
+
+    /// The position of the first block of the chunk, which its mesh is relative to
+    pub fn origin(&self) -> Vec3 {
+        Vec3::from(self.pos) * 16.0
+    }
+
+    /// Whether the mesh of the chunk can be seen, models like leaves can stick out a bit
+    pub fn is_visible(&self, frustum: &util::Frustum) -> bool {
+        let min = self.origin() - Vec3::ONE;
+        frustum.contains_box(min, min + Vec3::splat(18.0))
     }
 
-    /// Render if model present
-    pub fn render(&self) {
-        if let Some(x) = &self.model {
-            x.render();
+    /// The block next to a position inside the chunk and its state, taken from the neighboring
+    /// chunk if needed
+    fn neighbor(
+        &self,
+        world: &mut ChunkServer,
+        local: BlockPos,
+        side: BlockSide,
+    ) -> (BlockID, BlockState) {
+        let n = local + side;
+        match (self.get_block(n), self.get_state(n)) {
+            (Some(block), Some(state)) => (block, state),
+            _ => {
+                let p = BlockPos::new(
+                    self.pos.x * 16 + n.x,
+                    self.pos.y * 16 + n.y,
+                    self.pos.z * 16 + n.z,
+                );
+                (world.get_block(p).unwrap(), world.get_state(p).unwrap_or(0))
+            }
+        }
+    }
+
+    /// Whether the block at a position darkens the vertices next to it
+    fn occludes(&self, blocks: &BlockManager, world: &mut ChunkServer, pos: BlockPos) -> bool {
+        let local = BlockPos::new(
+            pos.x - self.pos.x * 16,
+            pos.y - self.pos.y * 16,
+            pos.z - self.pos.z * 16,
+        );
+        let block = match self.get_block(local) {
+            Some(x) => Some(x),
+            None => world.get_block(pos),
+        };
+        block.is_some_and(|x| blocks[x].opaque)
+    }
+
+    /// Ambient occlusion of the vertex at `v` with normal `n`, belonging to the block at `p`
+    ///
+    /// Looks at the two blocks next to the vertex and the one in the corner, in the layer in front
+    /// of the face. Faces which don't point along an axis aren't shaded.
+    fn vertex_ao(
+        &self,
+        blocks: &BlockManager,
+        world: &mut ChunkServer,
+        p: BlockPos,
+        v: Vec3,
+        n: Vec3,
+    ) -> f32 {
+        let axis = match (0..3).find(|x| n[*x].abs() > 0.99) {
+            Some(x) => x,
+            None => return 1.0,
+        };
+        let (b, c) = ((axis + 1) % 3, (axis + 2) % 3);
+        let mut layer = [p.x, p.y, p.z];
+        layer[axis] = (v[axis] + n[axis].signum() * 0.5).floor() as i32;
+        // which way the vertex is from the middle of the block, along the face
+        let dir = |i: usize| {
+            let d = v[i] - (layer[i] as f32 + 0.5);
+            if d > 0.01 {
+                1
+            } else if d < -0.01 {
+                -1
+            } else {
+                0
+            }
+        };
+        let (db, dc) = (dir(b), dir(c));
+        let mut occludes = |db: i32, dc: i32| {
+            if (db, dc) == (0, 0) {
+                return false;
+            }
+            let mut x = layer;
+            x[b] += db;
+            x[c] += dc;
+            self.occludes(blocks, world, BlockPos::new(x[0], x[1], x[2]))
+        };
+        let side1 = db != 0 && occludes(db, 0);
+        let side2 = dc != 0 && occludes(0, dc);
+        let corner = db != 0 && dc != 0 && occludes(db, dc);
+        if side1 && side2 {
+            AO_CURVE[0]
+        } else {
+            AO_CURVE[3 - side1 as usize - side2 as usize - corner as usize]
+        }
+    }
+
+    /// Computes the ambient occlusion of the vertices from `start` on, which belong to the block
+    /// at `p`, and flips their quads where needed
+    fn shade_mesh(
+        &self,
+        blocks: &BlockManager,
+        world: &mut ChunkServer,
+        data: &mut MeshData,
+        p: BlockPos,
+        start: usize,
+        first_index: usize,
+    ) {
+        data.ao.resize(start, 1.0);
+        for i in start..data.vertices.len() {
+            let v: Vec3 = data.vertices[i].into();
+            let n: Vec3 = data.normals[i].into();
+            let ao = self.vertex_ao(blocks, world, p, v, n);
+            data.ao.push(ao);
+        }
+        // quads are two triangles sharing the middle two vertices
+        let mut i = first_index;
+        while i + 1 < data.indices.len() {
+            let s = data.indices[i].0;
+            if data.indices[i] == (s, s + 1, s + 2) && data.indices[i + 1] == (s + 1, s + 2, s + 3)
+            {
+                let ao = &data.ao[s as usize..s as usize + 4];
+                let quad = quad_indices(s, [ao[0], ao[1], ao[2], ao[3]]);
+                data.indices[i] = quad[0];
+                data.indices[i + 1] = quad[1];
+                i += 2;
+            } else {
+                i += 1;
+            }
         }
     }
 
@@ -135,7 +587,12 @@ impl Chunk {
     ///
     /// Takes a couple of milliseconds
     pub fn update(&mut self, blocks: Arc<BlockManager>, world: &mut ChunkServer) {
-        let mut data = MeshData::new();
+        // one mesh for every render layer
+        let mut layers = [MeshData::new(), MeshData::new(), MeshData::new()];
+        let mut greedy = GreedyFaces::new();
+        let greedy_meshing = world.greedy_meshing();
+        self.detail_level = MeshDetail::level(world.chunk_distance(self.pos));
+        let detail = world.mesh_detail().with_level(self.detail_level);
         for i in 0..16 {
             for j in 0..16 {
                 for k in 0..16 {
@@ -144,46 +601,230 @@ impl Chunk {
                         self.pos.y * 16 + j as i32,
                         self.pos.z * 16 + k as i32,
                     );
-                    (blocks[self.blocks[i][j][k]].gen_mesh)(
-                        &mut data,
-                        p,
-                        util::BlockSides {
-                            top: if j == 15 {
-                                !blocks[world.get_block(p + BlockPos::new(0, 1, 0)).unwrap()].solid
-                            } else {
-                                !blocks[self.blocks[i][j + 1][k]].solid
-                            },
-                            bottom: if j == 0 {
-                                !blocks[world.get_block(p + BlockPos::new(0, -1, 0)).unwrap()].solid
-                            } else {
-                                !blocks[self.blocks[i][j - 1][k]].solid
-                            },
-                            left: if i == 0 {
-                                !blocks[world.get_block(p + BlockPos::new(-1, 0, 0)).unwrap()].solid
-                            } else {
-                                !blocks[self.blocks[i - 1][j][k]].solid
-                            },
-                            right: if i == 15 {
-                                !blocks[world.get_block(p + BlockPos::new(1, 0, 0)).unwrap()].solid
-                            } else {
-                                !blocks[self.blocks[i + 1][j][k]].solid
-                            },
-                            front: if k == 15 {
-                                !blocks[world.get_block(p + BlockPos::new(0, 0, 1)).unwrap()].solid
-                            } else {
-                                !blocks[self.blocks[i][j][k + 1]].solid
-                            },
-                            back: if k == 0 {
-                                !blocks[world.get_block(p + BlockPos::new(0, 0, -1)).unwrap()].solid
-                            } else {
-                                !blocks[self.blocks[i][j][k - 1]].solid
-                            },
-                        },
-                    );
+                    let block = &blocks[self.blocks[i][j][k]];
+                    let state = self.states[i][j][k];
+                    let local = BlockPos::new(i as i32, j as i32, k as i32);
+                    let mut visible = |side| {
+                        let (neighbor, neighbor_state) = self.neighbor(world, local, side);
+                        block.is_face_visible(state, side, &blocks[neighbor], neighbor_state)
+                    };
+                    let sides = util::BlockSides {
+                        top: visible(BlockSide::Top),
+                        bottom: visible(BlockSide::Bottom),
+                        left: visible(BlockSide::Left),
+                        right: visible(BlockSide::Right),
+                        front: visible(BlockSide::Front),
+                        back: visible(BlockSide::Back),
+                    };
+                    // full opaque blocks get merged with their neighbors later
+                    if greedy_meshing
+                        && block.is_greedy(state)
+                        && block.render_layer == RenderLayer::Opaque
+                    {
+                        for side in BlockSide::ALL {
+                            if *sides.get(side) {
+                                let mut face = block.greedy_face(side);
+                                let a: Vec3 = p.into();
+                                let corners = face_corners(side, a, a + Vec3::ONE);
+                                let normal: Vec3 = side.to_pos().into();
+                                for (ao, c) in face.ao.iter_mut().zip(corners) {
+                                    *ao = self.vertex_ao(&blocks, world, p, c, normal);
+                                }
+                                greedy.add(side, i, j, k, face);
+                            }
+                        }
+                    } else {
+                        let data = &mut layers[block.render_layer as usize];
+                        let (start, first_index) = (data.vertices.len(), data.indices.len());
+                        block.mesh(data, p, sides, state, detail);
+                        self.shade_mesh(&blocks, world, data, p, start, first_index);
+                    }
                 }
             }
         }
-        self.model = None;
-        self.model = Some(Model::new(&data));
+        let [mut opaque, cutout, translucent] = layers;
+        append_greedy(&mut opaque, self.origin(), &greedy);
+        self.connections = Connections::find(|i, j, k| {
+            blocks[self.blocks[i][j][k]].is_solid(self.states[i][j][k])
+        });
+        // free the old mesh first, so the new one can take its place
+        self.mesh = None;
+        let arena = world.mesh_arena();
+        self.mesh = Some(ChunkMesh {
+            opaque: arena.upload(&opaque, self.origin()),
+            cutout: arena.upload(&cutout, self.origin()),
+            translucent: arena.upload(&translucent, self.origin()),
+            translucent_data: (!translucent.indices.is_empty()).then_some(translucent),
+            sorted_for: None,
+        });
+    }
+
+    /// Sorts the translucent faces back to front as seen from the camera, so they are blended in
+    /// the right order
+    ///
+    /// They are only sorted again once the camera moves into another block, the order barely
+    /// changes before that
+    pub fn sort_translucent(&mut self, arena: &MeshArena, camera: Vec3) {
+        let origin = self.origin();
+        let block = BlockPos::new(
+            camera.x.floor() as i32,
+            camera.y.floor() as i32,
+            camera.z.floor() as i32,
+        );
+        let mesh = match &mut self.mesh {
+            Some(x) if x.sorted_for != Some(block) => x,
+            _ => return,
+        };
+        let data = match &mut mesh.translucent_data {
+            Some(x) => x,
+            None => return,
+        };
+        let vertices = &data.vertices;
+        let distance = |t: &(i32, i32, i32)| {
+            let corner = |i: i32| Vec3::from(vertices[i as usize]);
+            let center = (corner(t.0) + corner(t.1) + corner(t.2)) / 3.0;
+            center.distance_squared(camera)
+        };
+        data.indices
+            .sort_by(|a, b| distance(b).total_cmp(&distance(a)));
+        mesh.translucent = arena.upload(data, origin);
+        mesh.sorted_for = Some(block);
+    }
+}
+
+fn read_u8(file: &mut impl Read) -> Result<u8, std::io::Error> {
+    let mut buf = [0; 1];
+    file.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+/// Reads a string prefixed with its length
+fn read_str(file: &mut impl Read) -> Result<String, std::io::Error> {
+    let mut len = [0; 2];
+    file.read_exact(&mut len)?;
+    let mut buf = vec![0; u16::from_le_bytes(len) as usize];
+    file.read_exact(&mut buf)?;
+    String::from_utf8(buf).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+fn write_str(file: &mut impl Write, s: &str) -> Result<(), std::io::Error> {
+    file.write_all(&(s.len() as u16).to_le_bytes())?;
+    file.write_all(s.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blocks() -> Arc<BlockManager> {
+        Arc::new(BlockManager::from_files(&[String::from("blocks.json")]).unwrap())
+    }
+
+    /// An empty save directory for a test
+    fn save_dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("unsafe-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        String::from(dir.to_str().unwrap())
+    }
+
+    /// Saves a chunk with a stone block in it, returns the length of the file up to the palette
+    fn save_stone(blocks: &Arc<BlockManager>, dir: &str) -> u64 {
+        let mut c = Chunk::new(BlockPos::new(0, 5, 0), blocks, dir);
+        c.set_block(BlockPos::new(1, 2, 3), blocks.get("stone").unwrap(), 0);
+        c.save().unwrap();
+        16 * 16 * 16 * 2 + 4
+    }
+
+    fn truncate(dir: &str, len: u64) {
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .open(format!("{}/0-5-0.chunk", dir))
+            .unwrap();
+        file.set_len(len).unwrap();
+    }
+
+    #[test]
+    fn save_and_load() {
+        let (blocks, dir) = (blocks(), save_dir("save-and-load"));
+        save_stone(&blocks, &dir);
+        let c = Chunk::new(BlockPos::new(0, 5, 0), &blocks, &dir);
+        assert_eq!(c.get_block(BlockPos::new(1, 2, 3)), blocks.get("stone"));
+        assert_eq!(c.get_block(BlockPos::new(0, 0, 0)), blocks.get("air"));
+        assert!(!c.is_modified());
+    }
+
+    #[test]
+    fn chunk_without_palette_keeps_ids() {
+        let (blocks, dir) = (blocks(), save_dir("no-palette"));
+        let len = save_stone(&blocks, &dir);
+        truncate(&dir, len);
+        let mut c = Chunk::new(BlockPos::new(0, 5, 0), &blocks, &dir);
+        assert!(c.load().is_ok());
+        assert_eq!(c.get_block(BlockPos::new(1, 2, 3)), blocks.get("stone"));
+    }
+
+    #[test]
+    fn broken_palette_is_an_error() {
+        let (blocks, dir) = (blocks(), save_dir("broken-palette"));
+        let len = save_stone(&blocks, &dir);
+        // the palette's length and the first ID, without the name
+        truncate(&dir, len + 3);
+        let mut c = Chunk::new(BlockPos::new(0, 5, 0), &blocks, &dir);
+        assert!(c.load().is_err());
+        // the chunk is generated again instead
+        let c = Chunk::new(BlockPos::new(0, 5, 0), &blocks, &dir);
+        assert_eq!(c.get_block(BlockPos::new(1, 2, 3)), blocks.get("air"));
+    }
+
+    #[test]
+    fn failed_save_does_not_panic() {
+        let blocks = blocks();
+        let mut c = Chunk::new(BlockPos::new(0, 5, 0), &blocks, "/proc/unsafe-save");
+        c.set_block(BlockPos::new(1, 2, 3), blocks.get("stone").unwrap(), 0);
+        assert!(c.save().is_err());
+        drop(c);
+    }
+
+    #[test]
+    fn generated_surface_matches_the_chunk() {
+        let (blocks, dir) = (blocks(), save_dir("generated-surface"));
+        for pos in [
+            BlockPos::new(0, 0, 0),
+            BlockPos::new(3, -1, -2),
+            BlockPos::new(-7, 1, 5),
+        ] {
+            let chunk = Chunk::new(pos, &blocks, &dir);
+            assert_eq!(
+                Chunk::read_surface(pos, &blocks, &dir),
+                chunk.surface(&blocks)
+            );
+        }
+        assert!(!std::path::Path::new(&dir).exists());
+    }
+
+    #[test]
+    fn reading_a_surface_does_not_save_the_chunk() {
+        let (blocks, dir) = (blocks(), save_dir("read-surface"));
+        save_stone(&blocks, &dir);
+        // the stone is grass in the new block types, so the chunk is converted when it's read
+        let file = format!("{}/0-5-0.chunk", dir);
+        let mut data = std::fs::read(&file).unwrap();
+        let name = data.windows(5).position(|x| x == b"stone").unwrap();
+        data[name..name + 5].copy_from_slice(b"grass");
+        std::fs::write(&file, &data).unwrap();
+
+        let surface = Chunk::read_surface(BlockPos::new(0, 5, 0), &blocks, &dir);
+        assert_eq!(surface[0][1], Some((2, blocks.get("grass").unwrap())));
+        assert_eq!(surface[0][0], None);
+        assert_eq!(std::fs::read(&file).unwrap(), data);
+    }
+
+    #[test]
+    fn terrain_stays_within_the_amplitude() {
+        for x in -200..200 {
+            for z in -200..200 {
+                assert!(terrain_height(x, z).abs() <= TERRAIN_AMPLITUDE);
+            }
+        }
     }
 }
@@ -0,0 +1,129 @@
+use super::*;
+use meshdata::MeshData;
+use util::BlockSides;
+
+/// A block which is falling down, it isn't in any chunk until it lands
+pub struct FallingBlock {
+    pub block: BlockID,
+    pub state: BlockState,
+    /// Where the block started falling from, the model is generated there
+    pub start: BlockPos,
+    /// The center of the bottom of the block
+    pub pos: Vec3,
+    pub velocity: f32,
+    model: Model,
+}
+
+impl FallingBlock {
+    /// How many ticks an unsupported block waits before it starts falling
+    pub const DELAY: u32 = 2;
+    /// A bit less than half a block, boxes exactly as wide as the block below don't collide with
+    /// it
+    const RADIUS: f32 = 0.45;
+
+    /// `detail` should be the one of the world, so the block looks the same as before it fell
+    pub fn new(
+        blocks: &BlockManager,
+        pos: BlockPos,
+        block: BlockID,
+        state: BlockState,
+        detail: MeshDetail,
+    ) -> Self {
+        let mut data = MeshData::new();
+        blocks[block].mesh(&mut data, pos, BlockSides::from_fn(|_| true), state, detail);
+        Self {
+            block,
+            state,
+            start: pos,
+            pos: Vec3::from(pos) + Vec3::new(0.5, 0.0, 0.5),
+            velocity: 0.0,
+            model: Model::new(&data),
+        }
+    }
+
+    /// Moves the block down, returns the position where it lands if it hit the ground
+    pub fn update(&mut self, world: &mut ChunkServer) -> Option<BlockPos> {
+        let collisions = collisions::check_collisions(world, self.pos, Self::RADIUS, 1.0);
+        self.velocity += Game::GRAVITY;
+        if let Some(y) = collisions.top {
+            if self.velocity <= -y.0 {
+                self.pos.y = y.1;
+                // the block takes the first free space, even if it landed on something lower
+                // than a full block
+                return Some(BlockPos::new(
+                    self.pos.x.floor() as _,
+                    (self.pos.y - 0.01).ceil() as _,
+                    self.pos.z.floor() as _,
+                ));
+            }
+        }
+        self.pos.y += self.velocity;
+        None
+    }
+
+    /// Moves the model from where it was generated to where the block is now
+    pub fn model_matrix(&self) -> Mat4 {
+        Mat4::from_translation(self.pos - Vec3::new(0.5, 0.0, 0.5) - Vec3::from(self.start))
+    }
+
+    pub fn render(&self) {
+        self.model.render();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Drops a block from `height` blocks above `ground` until it lands, returns where it lands
+    fn drop_onto(world: &mut ChunkServer, ground: BlockPos, height: i32) -> Option<BlockPos> {
+        let blocks = world.block_manager.clone();
+        let sand = blocks.get("sand").unwrap();
+        let start = ground + BlockPos::new(0, height, 0);
+        let mut block = FallingBlock::new(&blocks, start, sand, 0, world.mesh_detail());
+        let landed = (0..200).find_map(|_| block.update(world));
+        // it never goes below the ground
+        assert!(block.pos.y >= ground.y as f32);
+        landed
+    }
+
+    #[test]
+    fn lands_on_a_full_block() {
+        let mut world = ChunkServer::for_tests("falling-full", 0);
+        let ground = BlockPos::new(8, 100, 8);
+        world
+            .set_block(ground, world.block_manager.get("stone").unwrap())
+            .unwrap();
+        let landed = drop_onto(&mut world, ground, 10);
+        assert_eq!(landed, Some(ground + BlockPos::new(0, 1, 0)));
+    }
+
+    #[test]
+    fn lands_above_a_slab() {
+        let mut world = ChunkServer::for_tests("falling-slab", 0);
+        let ground = BlockPos::new(8, 100, 8);
+        world
+            .set_block(ground, world.block_manager.get("slab").unwrap())
+            .unwrap();
+        // the bottom half of the block is taken, so it takes the block above
+        let landed = drop_onto(&mut world, ground, 6);
+        assert_eq!(landed, Some(ground + BlockPos::new(0, 1, 0)));
+    }
+
+    #[test]
+    fn keeps_falling_through_air() {
+        let mut world = ChunkServer::for_tests("falling-air", 0);
+        let blocks = world.block_manager.clone();
+        let start = BlockPos::new(8, 100, 8);
+        let sand = blocks.get("sand").unwrap();
+        let mut block = FallingBlock::new(&blocks, start, sand, 0, world.mesh_detail());
+        for _ in 0..5 {
+            assert_eq!(block.update(&mut world), None);
+        }
+        assert!(block.pos.y < start.y as f32);
+        assert_eq!(
+            block.model_matrix().transform_point3(Vec3::ZERO).y,
+            block.pos.y - 100.0
+        );
+    }
+}
@@ -0,0 +1,554 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use super::*;
+use block::append_greedy_quad;
+use chunk::TERRAIN_AMPLITUDE;
+use meshdata::MeshData;
+use util::BlockSide;
+
+/// The highest full cube of every 2x2 columns of a chunk, its height inside the chunk and its ID.
+/// None if there is no full cube in the columns.
+pub type Surface = [[Option<(u8, BlockID)>; 8]; 8];
+
+/// A square of far away terrain, made of `CELLS` x `CELLS` columns which are `2^level` blocks
+/// wide
+#[derive(Hash, PartialEq, Eq, Clone, Copy, Debug)]
+struct Tile {
+    level: u32,
+    x: i32,
+    z: i32,
+}
+
+impl Tile {
+    const CELLS: i32 = 16;
+
+    /// How many blocks a cell is wide
+    fn step(self) -> i32 {
+        1 << self.level
+    }
+
+    /// How many blocks the tile is wide
+    fn size(self) -> i32 {
+        Self::CELLS << self.level
+    }
+
+    fn children(self) -> [Tile; 4] {
+        let (level, x, z) = (self.level - 1, self.x * 2, self.z * 2);
+        [
+            Tile { level, x, z },
+            Tile { level, x: x + 1, z },
+            Tile { level, x, z: z + 1 },
+            Tile {
+                level,
+                x: x + 1,
+                z: z + 1,
+            },
+        ]
+    }
+
+    fn parent(self) -> Tile {
+        Tile {
+            level: self.level + 1,
+            x: self.x.div_euclid(2),
+            z: self.z.div_euclid(2),
+        }
+    }
+
+    fn bounds(self) -> (Vec3, Vec3) {
+        let size = self.size() as f32;
+        let min = Vec3::new(
+            self.x as f32 * size,
+            FarTerrain::BOTTOM as f32,
+            self.z as f32 * size,
+        );
+        (
+            min,
+            Vec3::new(min.x + size, FarTerrain::TOP as f32, min.z + size),
+        )
+    }
+
+    /// Horizontal distance from a position to the closest point of the tile
+    fn distance(self, pos: Vec3) -> f32 {
+        let (min, max) = self.bounds();
+        let p = Vec2::new(pos.x, pos.z);
+        (p.clamp(Vec2::new(min.x, min.z), Vec2::new(max.x, max.z)) - p).length()
+    }
+
+    /// The first and the last chunk column (x and z) which the mesh of the tile is made from,
+    /// including the cells around the tile
+    fn columns(self) -> ((i32, i32), (i32, i32)) {
+        let (min, max) = (self.size() * self.x, self.size() * self.z);
+        let first = |x: i32| (x - self.step()).div_euclid(16);
+        let last = |x: i32| (x + self.size() + self.step() - 1).div_euclid(16);
+        ((first(min), first(max)), (last(min), last(max)))
+    }
+}
+
+/// Which chunk columns around the camera are drawn by the chunks, the far terrain isn't drawn
+/// there
+#[derive(Clone, PartialEq, Default)]
+pub struct HiddenChunks {
+    /// The first chunk column (x and z)
+    pub start: (i32, i32),
+    /// How many columns there are in each direction
+    pub size: i32,
+    /// Whether every column has a mesh, by x and then z
+    pub meshed: Vec<bool>,
+}
+
+impl HiddenChunks {
+    fn is_hidden(&self, x: i32, z: i32) -> bool {
+        let (i, j) = (x - self.start.0, z - self.start.1);
+        i >= 0
+            && j >= 0
+            && i < self.size
+            && j < self.size
+            && self.meshed[(i * self.size + j) as usize]
+    }
+
+    /// Whether every column under the tile is hidden
+    fn covers(&self, tile: Tile) -> bool {
+        let size = tile.size() / 16;
+        (0..size).all(|i| (0..size).all(|j| self.is_hidden(tile.x * size + i, tile.z * size + j)))
+    }
+
+    /// One pixel for every column, white if it's hidden
+    fn image(&self) -> image::RgbaImage {
+        image::RgbaImage::from_fn(self.size as u32, self.size as u32, |i, j| {
+            let hidden = self.meshed[(i * self.size as u32 + j) as usize];
+            image::Rgba([if hidden { 255 } else { 0 }; 4])
+        })
+    }
+}
+
+/// Coarse meshes of the terrain beyond the loaded chunks, made from the surfaces of the chunks
+///
+/// The terrain is split into a quadtree of tiles, which get twice as coarse each time the
+/// distance to the camera doubles. The tiles are made from a cache of the highest blocks of every
+/// chunk, the chunks which aren't loaded are read from their files (or generated) just for that.
+/// When a tile is replaced by finer or coarser ones, they fade into each other.
+pub struct FarTerrain {
+    /// How far the terrain reaches from the camera, in blocks. 0 if it's disabled.
+    distance: i32,
+    save_dir: String,
+    tiles: HashMap<Tile, ArenaMesh>,
+    /// Tiles which have to be meshed again because their chunks changed, they're drawn until then
+    stale: HashSet<Tile>,
+    /// The tiles which should be drawn, some of them might not be meshed yet
+    wanted: Vec<Tile>,
+    /// The chunk the camera is in and the hidden chunks which `wanted` was chosen for
+    chosen_for: Option<(BlockPos, HiddenChunks)>,
+    /// The surfaces of the chunks between `BOTTOM` and `TOP`
+    surfaces: HashMap<BlockPos, Surface>,
+    /// Marks the hidden chunks for the shader, None until they've been found
+    hidden_texture: Option<Texture>,
+    /// The frame in which every drawn tile started fading in
+    shown: HashMap<Tile, u64>,
+    /// The frame in which tiles which aren't drawn anymore started fading out
+    fading_out: HashMap<Tile, u64>,
+    frame: u64,
+}
+
+impl FarTerrain {
+    /// The finest and the coarsest level of the tiles
+    const MIN_LEVEL: u32 = 1;
+    const MAX_LEVEL: u32 = 4;
+    /// Tiles closer to the camera than this many times their size get split into four
+    const DETAIL: f32 = 1.5;
+    /// The terrain is between these heights, the chunks which the generated terrain can reach.
+    /// The sides of the tiles reach down to the bottom so there are no gaps between different
+    /// levels.
+    const BOTTOM: i32 = (-TERRAIN_AMPLITUDE).div_euclid(16) * 16;
+    const TOP: i32 = (TERRAIN_AMPLITUDE.div_euclid(16) + 1) * 16;
+    /// How many frames it takes for a tile to fade in or out
+    const FADE_FRAMES: u64 = 30;
+    /// How many chunks which aren't loaded can be read for their surface each frame, for every
+    /// tile which can be meshed
+    const CHUNKS_PER_TILE: u32 = 4;
+
+    pub fn new(distance: i32, save_dir: &str) -> Self {
+        Self {
+            distance,
+            save_dir: String::from(save_dir),
+            tiles: HashMap::new(),
+            stale: HashSet::new(),
+            wanted: Vec::new(),
+            chosen_for: None,
+            surfaces: HashMap::new(),
+            hidden_texture: None,
+            shown: HashMap::new(),
+            fading_out: HashMap::new(),
+            frame: 0,
+        }
+    }
+
+    /// The chunk heights which the far terrain is made from, from the top
+    fn chunk_heights() -> impl Iterator<Item = i32> {
+        ((Self::BOTTOM >> 4)..=((Self::TOP - 1) >> 4)).rev()
+    }
+
+    /// Which columns around a chunk position have all of their chunks between `BOTTOM` and `TOP`
+    /// meshed, `radius` chunks in every direction
+    pub fn hidden_chunks(
+        center: BlockPos,
+        radius: i32,
+        meshed: impl Fn(BlockPos) -> bool,
+    ) -> HiddenChunks {
+        let start = (center.x - radius, center.z - radius);
+        let size = radius * 2;
+        let meshed = (0..size)
+            .flat_map(|i| (0..size).map(move |j| (start.0 + i, start.1 + j)))
+            .map(|(x, z)| Self::chunk_heights().all(|y| meshed(BlockPos::new(x, y, z))))
+            .collect();
+        HiddenChunks {
+            start,
+            size,
+            meshed,
+        }
+    }
+
+    /// Chooses the tiles around the camera and meshes at most `limit` of them, nothing is drawn
+    /// over the `hidden` chunks
+    pub fn update(
+        &mut self,
+        camera: BlockPos,
+        hidden: &HiddenChunks,
+        blocks: &Arc<BlockManager>,
+        arena: &MeshArena,
+        limit: u32,
+    ) {
+        if self.distance <= 0 {
+            return;
+        }
+        self.frame += 1;
+        let chunk = BlockPos::new(camera.x >> 4, camera.y >> 4, camera.z >> 4);
+        let moved = self.chosen_for.as_ref().is_none_or(|x| x.0 != chunk);
+        if moved {
+            self.forget_far_surfaces(camera.into());
+        }
+        if moved || self.chosen_for.as_ref().is_some_and(|x| x.1 != *hidden) {
+            let image = hidden.image();
+            match &self.hidden_texture {
+                Some(x) if self.chosen_for.as_ref().map(|x| x.1.size) == Some(hidden.size) => {
+                    x.update(0, 0, 0, &image)
+                }
+                _ => self.hidden_texture = Some(Texture::from_image(&image, 0)),
+            }
+            self.chosen_for = Some((chunk, hidden.clone()));
+            self.wanted = self.choose_tiles(camera.into(), hidden);
+        }
+
+        let mut missing: Vec<Tile> = self
+            .wanted
+            .iter()
+            .filter(|x| !self.tiles.contains_key(x))
+            .copied()
+            .collect();
+        // coarse tiles first, they cover more of the holes
+        missing.sort_by_key(|x| Reverse(x.level));
+        missing.extend(self.stale.iter().copied());
+        let mut loads = limit * Self::CHUNKS_PER_TILE;
+        for tile in missing.into_iter().take(limit as usize) {
+            if !self.load_surfaces(tile, blocks, &mut loads) {
+                break;
+            }
+            let origin = Vec3::new(
+                (tile.x * Tile::CELLS) as f32,
+                (Self::BOTTOM / tile.step()) as f32,
+                (tile.z * Tile::CELLS) as f32,
+            );
+            let mesh = arena.upload_scaled(&self.mesh(tile, blocks), origin, tile.step() as f32);
+            self.tiles.insert(tile, mesh);
+            self.stale.remove(&tile);
+        }
+
+        // fade between the tiles which were drawn before and the ones which are drawn now. A tile
+        // which is only partly faded in or out continues from there.
+        let frame = self.frame;
+        let reverse = |start: u64| (frame + frame - start).saturating_sub(Self::FADE_FRAMES);
+        let drawn: HashSet<Tile> = self.drawn_tiles().into_iter().collect();
+        for &tile in &drawn {
+            if !self.shown.contains_key(&tile) {
+                let start = self.fading_out.remove(&tile).map_or(frame, reverse);
+                self.shown.insert(tile, start);
+            }
+        }
+        let mut stopped = Vec::new();
+        self.shown.retain(|k, v| {
+            let keep = drawn.contains(k);
+            if !keep {
+                stopped.push((
+                    *k,
+                    reverse((*v).max(frame.saturating_sub(Self::FADE_FRAMES))),
+                ));
+            }
+            keep
+        });
+        self.fading_out.extend(stopped);
+        self.fading_out
+            .retain(|_, v| frame - *v < Self::FADE_FRAMES);
+
+        let wanted: HashSet<Tile> = self.wanted.iter().copied().collect();
+        let fading_out = &self.fading_out;
+        self.tiles
+            .retain(|k, _| drawn.contains(k) || wanted.contains(k) || fading_out.contains_key(k));
+        let tiles = &self.tiles;
+        self.stale.retain(|x| tiles.contains_key(x));
+    }
+
+    /// The leaves of the quadtree within the distance, without the tiles covered by the hidden
+    /// chunks
+    fn choose_tiles(&self, camera: Vec3, hidden: &HiddenChunks) -> Vec<Tile> {
+        let size = Tile::CELLS << Self::MAX_LEVEL;
+        let start = |x: f32| (x as i32 - self.distance).div_euclid(size);
+        let end = |x: f32| (x as i32 + self.distance).div_euclid(size);
+        let mut stack = Vec::new();
+        for x in start(camera.x)..=end(camera.x) {
+            for z in start(camera.z)..=end(camera.z) {
+                stack.push(Tile {
+                    level: Self::MAX_LEVEL,
+                    x,
+                    z,
+                });
+            }
+        }
+        let mut tiles = Vec::new();
+        while let Some(tile) = stack.pop() {
+            let distance = tile.distance(camera);
+            if distance > self.distance as f32 || hidden.covers(tile) {
+                continue;
+            }
+            if tile.level > Self::MIN_LEVEL && distance < Self::DETAIL * tile.size() as f32 {
+                stack.extend(tile.children());
+            } else {
+                tiles.push(tile);
+            }
+        }
+        tiles
+    }
+
+    /// The meshed tiles to draw, a missing tile is replaced by the closest larger one
+    fn drawn_tiles(&self) -> Vec<Tile> {
+        let mut drawn = HashSet::new();
+        for &tile in &self.wanted {
+            let mut tile = tile;
+            while !self.tiles.contains_key(&tile) && tile.level < Self::MAX_LEVEL {
+                tile = tile.parent();
+            }
+            if self.tiles.contains_key(&tile) {
+                drawn.insert(tile);
+            }
+        }
+        drawn.into_iter().collect()
+    }
+
+    /// Makes sure the surfaces of every chunk the tile is made from are known, reads at most
+    /// `loads` chunks which aren't cached yet. Returns whether all of them are known.
+    fn load_surfaces(&mut self, tile: Tile, blocks: &Arc<BlockManager>, loads: &mut u32) -> bool {
+        let (first, last) = tile.columns();
+        for x in first.0..=last.0 {
+            for z in first.1..=last.1 {
+                for y in Self::chunk_heights() {
+                    let pos = BlockPos::new(x, y, z);
+                    if self.surfaces.contains_key(&pos) {
+                        continue;
+                    }
+                    if *loads == 0 {
+                        return false;
+                    }
+                    *loads -= 1;
+                    let surface = Chunk::read_surface(pos, blocks, &self.save_dir);
+                    self.surfaces.insert(pos, surface);
+                }
+            }
+        }
+        true
+    }
+
+    /// Updates the surface of a chunk, the tiles made from it get meshed again if it changed
+    pub fn record(&mut self, chunk: &Chunk, blocks: &BlockManager) {
+        if self.distance <= 0 || !Self::chunk_heights().any(|y| y == chunk.pos.y) {
+            return;
+        }
+        let surface = chunk.surface(blocks);
+        match self.surfaces.insert(chunk.pos, surface) {
+            Some(old) if old != surface => (),
+            _ => return,
+        }
+        for &tile in self.tiles.keys() {
+            let (first, last) = tile.columns();
+            if (first.0..=last.0).contains(&chunk.pos.x)
+                && (first.1..=last.1).contains(&chunk.pos.z)
+            {
+                self.stale.insert(tile);
+            }
+        }
+    }
+
+    /// Forgets the surfaces of the chunks which are too far away to be needed
+    fn forget_far_surfaces(&mut self, camera: Vec3) {
+        let reach = (self.distance + (Tile::CELLS << Self::MAX_LEVEL)) as f32;
+        self.surfaces.retain(|k, _| {
+            let center = Vec2::new(k.x as f32, k.z as f32) * 16.0 + 8.0;
+            (center - Vec2::new(camera.x, camera.z)).abs().max_element() <= reach
+        });
+    }
+
+    /// The highest block of a cell and its height, None if it has no blocks
+    fn cell(&self, tile: Tile, i: i32, j: i32) -> Option<(i32, BlockID)> {
+        let step = tile.step();
+        let x = (tile.x * Tile::CELLS + i) * step;
+        let z = (tile.z * Tile::CELLS + j) * step;
+        let mut highest: Option<(i32, BlockID)> = None;
+        // the surfaces have one sample for every 2x2 columns
+        for sx in (x / 2)..((x + step) / 2) {
+            for sz in (z / 2)..((z + step) / 2) {
+                let (cx, cz) = (sx.div_euclid(8), sz.div_euclid(8));
+                let (lx, lz) = (sx.rem_euclid(8) as usize, sz.rem_euclid(8) as usize);
+                let sample = Self::chunk_heights().find_map(|y| {
+                    let (h, id) = self.surfaces.get(&BlockPos::new(cx, y, cz))?[lx][lz]?;
+                    Some((y * 16 + h as i32, id))
+                });
+                if sample.is_some_and(|s| highest.is_none_or(|h| s.0 > h.0)) {
+                    highest = sample;
+                }
+            }
+        }
+        highest
+    }
+
+    /// A column of the highest block for every cell, in units of cells
+    fn mesh(&self, tile: Tile, blocks: &BlockManager) -> MeshData {
+        let mut data = MeshData::new();
+        let step = tile.step();
+        let bottom = (Self::BOTTOM / step) as f32;
+        // the cells next to the tile are included
+        let width = Tile::CELLS + 2;
+        let cells: Vec<Option<(i32, BlockID)>> = (-1..=Tile::CELLS)
+            .flat_map(|i| (-1..=Tile::CELLS).map(move |j| (i, j)))
+            .map(|(i, j)| self.cell(tile, i, j))
+            .collect();
+        let cell = |i: i32, j: i32| cells[((i + 1) * width + j + 1) as usize];
+        let height = |i: i32, j: i32| cell(i, j).map_or(bottom, |x| (x.0 + 1) as f32 / step as f32);
+        for i in 0..Tile::CELLS {
+            for j in 0..Tile::CELLS {
+                let (y, id) = match cell(i, j) {
+                    Some(x) => x,
+                    None => continue,
+                };
+                let block = &blocks[id];
+                let pos = BlockPos::new(
+                    (tile.x * Tile::CELLS + i) * step + step / 2,
+                    y,
+                    (tile.z * Tile::CELLS + j) * step + step / 2,
+                );
+                let h = height(i, j);
+                let a = Vec3::new(
+                    (tile.x * Tile::CELLS + i) as f32,
+                    bottom,
+                    (tile.z * Tile::CELLS + j) as f32,
+                );
+                let b = Vec3::new(a.x + 1.0, h, a.z + 1.0);
+                if let Some(face) = block.far_face(BlockSide::Top, pos) {
+                    append_greedy_quad(&mut data, Vec3::ZERO, BlockSide::Top, a, b, face);
+                }
+                for side in [
+                    BlockSide::Left,
+                    BlockSide::Right,
+                    BlockSide::Front,
+                    BlockSide::Back,
+                ] {
+                    let n = side.to_pos();
+                    let (ni, nj) = (i + n.x, j + n.z);
+                    // the sides of the tile reach all the way down, the neighboring tile could
+                    // be coarser or missing
+                    let edge = ni < 0 || nj < 0 || ni >= Tile::CELLS || nj >= Tile::CELLS;
+                    let low = if edge { bottom } else { height(ni, nj) };
+                    if low >= h {
+                        continue;
+                    }
+                    if let Some(face) = block.far_face(side, pos) {
+                        let a = Vec3::new(a.x, low, a.z);
+                        append_greedy_quad(&mut data, Vec3::ZERO, side, a, b, face);
+                    }
+                }
+            }
+        }
+        data
+    }
+
+    /// Draws the meshed tiles inside the frustum, returns how many were drawn
+    ///
+    /// Nothing is drawn over the hidden chunks. Tiles which are fading in or out are drawn one by
+    /// one, with a part of their pixels.
+    pub fn draw(&self, arena: &MeshArena, view: &mut RenderView, frustum: &util::Frustum) -> usize {
+        let hidden = match (&self.chosen_for, &self.hidden_texture) {
+            (Some((_, hidden)), Some(texture)) => {
+                texture.bind_to(1);
+                hidden
+            }
+            _ => return 0,
+        };
+        let (x, z) = (hidden.start.0 as f32 * 16.0, hidden.start.1 as f32 * 16.0);
+        let size = hidden.size as f32 * 16.0;
+        view.set_hidden_chunks(
+            Vec3::new(x, (Self::BOTTOM - 1) as f32, z),
+            Vec3::new(x + size, (Self::TOP + 1) as f32, z + size),
+        );
+
+        let visible = |x: &Tile| {
+            let (min, max) = x.bounds();
+            frustum.contains_box(min, max)
+        };
+        let progress = |start: u64| (self.frame - start) as f32 / Self::FADE_FRAMES as f32;
+        let mut steady = Vec::new();
+        let mut fading = Vec::new();
+        for tile in self.drawn_tiles().into_iter().filter(visible) {
+            let p = self.shown.get(&tile).map_or(1.0, |&x| progress(x));
+            match self.tiles.get(&tile) {
+                Some(mesh) if p >= 1.0 => steady.push(mesh),
+                Some(mesh) => fading.push((mesh, (0.0, p))),
+                None => (),
+            }
+        }
+        for (tile, &start) in &self.fading_out {
+            if let Some(mesh) = self.tiles.get(tile).filter(|_| visible(tile)) {
+                fading.push((mesh, (progress(start), 1.0)));
+            }
+        }
+        let drawn = steady.len() + fading.len();
+        arena.draw(steady.into_iter(), |x| view.set_model(x));
+        for (mesh, (start, end)) in fading {
+            view.set_dither_range(start, end);
+            arena.draw(std::iter::once(mesh), |x| view.set_model(x));
+        }
+        view.set_dither_range(0.0, 1.0);
+        view.set_hidden_chunks(Vec3::ZERO, Vec3::ZERO);
+        drawn
+    }
+
+    /// How far away anything is drawn
+    pub fn distance(&self) -> i32 {
+        self.distance
+    }
+
+    /// Forgets every mesh and surface, e.g. when the block types change
+    pub fn clear(&mut self) {
+        self.tiles.clear();
+        self.stale.clear();
+        self.surfaces.clear();
+        self.shown.clear();
+        self.fading_out.clear();
+    }
+
+    /// How many bytes the meshes take up on the GPU
+    pub fn memory_usage(&self) -> usize {
+        self.tiles.values().map(|x| x.memory_usage()).sum()
+    }
+
+    pub fn tile_count(&self) -> usize {
+        self.tiles.len()
+    }
+}
@@ -1,9 +1,17 @@
 use super::*;
-use block::{BlockID, BlockManager};
+use block::{BlockID, BlockManager, BlockState, MeshDetail};
 
+pub mod biome;
 mod chunk;
+mod falling;
+mod lod;
 mod server;
+pub mod tick;
+mod visibility;
 
 pub use server::ChunkServer;
 
 use chunk::Chunk;
+use falling::FallingBlock;
+use lod::{FarTerrain, Surface};
+use visibility::Connections;
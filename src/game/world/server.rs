@@ -1,12 +1,22 @@
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::collections::VecDeque;
 use std::sync::Arc;
 
 use super::*;
+use block::RenderLayer;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tick::{TickBehavior, TickFn};
+use util::BlockSide;
 
 pub struct ChunkServer {
     chunks: HashMap<BlockPos, Box<Chunk>>,
     texture: Rc<Texture>,
+    /// Holds the meshes of all of the chunks
+    arena: MeshArena,
+    /// Coarse terrain beyond the view distance
+    far_terrain: FarTerrain,
 
     /// queue of the chunks to be generated, used so that we don't generate all the chunks at once
     gen_queue: VecDeque<BlockPos>,
@@ -17,6 +27,36 @@ pub struct ChunkServer {
     view_distance: i32,
     /// How many chunks should be handled each frame
     loading_limit: u32,
+    /// How much memory the loaded chunks and their meshes may use, in bytes
+    ///
+    /// Chunks inside the view distance are never unloaded, even if they don't fit
+    memory_budget: usize,
+    /// How much memory the loaded chunks and their meshes use, in bytes, kept up to date by
+    /// `take_chunk` and `put_chunk`
+    memory_used: usize,
+    /// Incremented on every update, used to find the least recently used chunks
+    frame: u64,
+    /// The image of every animated texture which is currently in the texture
+    animation_frames: Vec<Option<usize>>,
+
+    /// How many ticks happen every second
+    tick_rate: f32,
+    /// How many random blocks get ticked in every chunk on every tick
+    random_tick_speed: u32,
+    /// How many ticks have happened since the game started
+    ticks: u64,
+    /// The tick behaviors, by block name
+    tick_behaviors: HashMap<String, TickBehavior>,
+    /// The tick behavior of every block ID
+    tick_table: Vec<TickBehavior>,
+    /// Picks the blocks which get random ticks, and is used by the ticks for anything random
+    pub rng: StdRng,
+    /// Blocks which are falling down right now
+    falling: Vec<FallingBlock>,
+    /// Whether the faces of full blocks are merged into larger quads
+    greedy_meshing: bool,
+    /// Seed of the random parts of the block models
+    seed: u64,
 
     /// Position of the camera (or anything else loading the chunks)
     pos: BlockPos,
@@ -33,13 +73,32 @@ impl ChunkServer {
         block_manager: Arc<BlockManager>,
         config: &json::JsonValue,
     ) -> Self {
+        let save_dir = config["save_dir"].as_str().unwrap();
         Self {
             chunks: HashMap::new(),
             texture,
+            arena: MeshArena::new(),
+            far_terrain: FarTerrain::new(
+                config["far_terrain_distance"].as_i32().unwrap_or(1024),
+                save_dir,
+            ),
             view_distance: config["view_distance"].as_i32().unwrap(),
             loading_limit: config["loading_limit"].as_u32().unwrap(),
-            save_dir: String::from(config["save_dir"].as_str().unwrap()),
-            pos: BlockPos::new(std::i32::MAX, std::i32::MAX, std::i32::MAX),
+            memory_budget: config["memory_budget"].as_usize().unwrap_or(512) * 1024 * 1024,
+            memory_used: 0,
+            frame: 0,
+            animation_frames: Vec::new(),
+            tick_rate: config["tick_rate"].as_f32().unwrap_or(20.0),
+            random_tick_speed: config["random_tick_speed"].as_u32().unwrap_or(3),
+            ticks: 0,
+            tick_behaviors: HashMap::new(),
+            tick_table: Vec::new(),
+            rng: StdRng::from_entropy(),
+            falling: Vec::new(),
+            greedy_meshing: config["greedy_meshing"].as_bool().unwrap_or(true),
+            seed: config["seed"].as_u64().unwrap_or(0),
+            save_dir: String::from(save_dir),
+            pos: BlockPos::new(i32::MAX, i32::MAX, i32::MAX),
             block_manager,
             gen_queue: VecDeque::new(),
         }
@@ -47,14 +106,39 @@ impl ChunkServer {
 
     /// Load new and unload old chunks, can be safely called every frame
     pub fn update(&mut self, camera: BlockPos) {
+        self.frame += 1;
         self.handle_generation(self.loading_limit);
         let new_pos = BlockPos::new(camera.x >> 4, camera.y >> 4, camera.z >> 4);
         if new_pos != self.pos {
             self.pos = new_pos;
             // remove old chunks
             // We keep chunks loaded even when they are 2 units away from the view distance
-            self.chunks
-                .retain(|k, _| Self::keep_chunk(self.pos, self.view_distance + 2, k));
+            let (pos, distance) = (self.pos, self.view_distance + 2);
+            self.place_falling_blocks(|x| !Self::keep_chunk(pos, distance, &x));
+            let mut freed = 0;
+            self.chunks.retain(|k, v| {
+                let keep = Self::keep_chunk(self.pos, self.view_distance + 2, k);
+                if !keep {
+                    freed += v.memory_usage();
+                }
+                keep
+            });
+            self.memory_used -= freed;
+            // remesh the chunks whose level of detail changed
+            let remesh: Vec<BlockPos> = self
+                .chunks
+                .iter()
+                .filter(|(k, v)| {
+                    v.mesh.is_some()
+                        && v.detail_level != MeshDetail::level(self.chunk_distance(**k))
+                })
+                .map(|(k, _)| *k)
+                .collect();
+            for pos in remesh {
+                if !self.gen_queue.contains(&pos) {
+                    self.request_generation(pos);
+                }
+            }
             // insert new chunks
             for i in (self.pos.x - self.view_distance)..(self.pos.x + self.view_distance) {
                 for j in (self.pos.y - self.view_distance)..(self.pos.y + self.view_distance) {
@@ -62,7 +146,7 @@ impl ChunkServer {
                         let pos = BlockPos::new(i, j, k);
 
                         if match self.chunks.get(&pos) {
-                            Some(x) => x.model.is_none(),
+                            Some(x) => x.mesh.is_none(),
                             None => true,
                         } && !self.gen_queue.contains(&pos)
                         {
@@ -72,6 +156,23 @@ impl ChunkServer {
                 }
             }
         }
+        self.enforce_memory_budget();
+        // the far terrain is hidden where the chunks are drawn
+        let hidden = FarTerrain::hidden_chunks(self.pos, self.view_distance + 2, |x| {
+            self.chunks.get(&x).is_some_and(|x| x.mesh.is_some())
+        });
+        self.far_terrain.update(
+            camera,
+            &hidden,
+            &self.block_manager,
+            &self.arena,
+            self.loading_limit,
+        );
+    }
+
+    /// How far away anything is drawn, in blocks
+    pub fn draw_distance(&self) -> f32 {
+        (self.view_distance * 16).max(self.far_terrain.distance()) as f32
     }
 
     // Add a chunk to the generation queue
@@ -82,26 +183,23 @@ impl ChunkServer {
     // Generate at most `count` chunks from the queue
     fn handle_generation(&mut self, count: u32) {
         let mut generated_chunks = 0;
-        loop {
-            if let Some(x) = self.gen_queue.pop_front() {
-                // If the chunk is not needed anymore, don't generate it
-                if !Self::keep_chunk(self.pos, self.view_distance, &x) {
-                    continue;
-                }
+        while let Some(x) = self.gen_queue.pop_front() {
+            // If the chunk is not needed anymore, don't generate it
+            if !Self::keep_chunk(self.pos, self.view_distance, &x) {
+                continue;
+            }
 
-                let bm = self.block_manager.clone();
-                let mut c = match self.chunks.remove(&x) {
-                    Some(a) => a,
-                    None => Box::new(Chunk::new(x, &self.block_manager, &self.save_dir)),
-                };
-                c.update(bm, self);
-                self.chunks.insert(x, c);
+            let bm = self.block_manager.clone();
+            let mut c = match self.take_chunk(x) {
+                Some(a) => a,
+                None => Box::new(Chunk::new(x, &self.block_manager, &self.save_dir)),
+            };
+            c.update(bm, self);
+            c.last_used = self.frame;
+            self.put_chunk(x, c);
 
-                // Only add to the count when the generated funtion has actually been executed
-                generated_chunks += 1;
-            } else {
-                break;
-            }
+            // Only add to the count when the generated funtion has actually been executed
+            generated_chunks += 1;
             if generated_chunks >= count {
                 break;
             }
@@ -118,26 +216,468 @@ impl ChunkServer {
             && k.z < camera.z + view_distance
     }
 
+    /// Unloads the least recently used chunks outside of the view distance until the loaded chunks
+    /// fit into the memory budget
+    fn enforce_memory_budget(&mut self) {
+        if self.memory_used <= self.memory_budget {
+            return;
+        }
+        let mut candidates: Vec<(u64, BlockPos)> = self
+            .chunks
+            .iter()
+            .filter(|(k, _)| !Self::keep_chunk(self.pos, self.view_distance, k))
+            .map(|(k, v)| (v.last_used, *k))
+            .collect();
+        candidates.sort_unstable_by_key(|x| x.0);
+        for (_, pos) in candidates {
+            if self.memory_used <= self.memory_budget {
+                break;
+            }
+            self.evict_chunk(pos);
+        }
+    }
+
+    /// Removes a chunk from memory, saving it first if it has been modified
+    ///
+    /// If the chunk can't be saved, it stays loaded
+    fn evict_chunk(&mut self, pos: BlockPos) -> Option<()> {
+        self.place_falling_blocks(|x| x == pos);
+        let c = self.chunks.get_mut(&pos)?;
+        if c.is_modified() {
+            if let Err(e) = c.save() {
+                println!("cannot save chunk {:?}: {}", pos, e);
+                return None;
+            }
+        }
+        self.take_chunk(pos).map(|_| ())
+    }
+
+    /// Removes a chunk from the hash map and stops counting its memory
+    fn take_chunk(&mut self, pos: BlockPos) -> Option<Box<Chunk>> {
+        let c = self.chunks.remove(&pos)?;
+        self.memory_used -= c.memory_usage();
+        Some(c)
+    }
+
+    /// Puts a chunk into the hash map and counts its memory, replacing the chunk which was there
+    ///
+    /// The far terrain is told about the chunk's blocks, which might have changed
+    fn put_chunk(&mut self, pos: BlockPos, c: Box<Chunk>) {
+        self.far_terrain.record(&c, &self.block_manager);
+        self.memory_used += c.memory_usage();
+        if let Some(old) = self.chunks.insert(pos, c) {
+            self.memory_used -= old.memory_usage();
+        }
+    }
+
+    pub fn greedy_meshing(&self) -> bool {
+        self.greedy_meshing
+    }
+
+    pub fn mesh_arena(&self) -> &MeshArena {
+        &self.arena
+    }
+
+    /// How many chunks away from the camera's chunk a chunk is, along the axis where it's furthest
+    pub fn chunk_distance(&self, chunk: BlockPos) -> u32 {
+        chunk
+            .x
+            .abs_diff(self.pos.x)
+            .max(chunk.y.abs_diff(self.pos.y))
+            .max(chunk.z.abs_diff(self.pos.z))
+    }
+
+    /// How the blocks of the world are meshed at the full level of detail
+    pub fn mesh_detail(&self) -> MeshDetail {
+        MeshDetail {
+            seed: self.seed,
+            ..Default::default()
+        }
+    }
+
+    /// Number of vertices in the meshes of all loaded chunks
+    pub fn vertex_count(&self) -> usize {
+        self.chunks.values().map(|x| x.vertex_count()).sum()
+    }
+
+    /// Approximate amount of memory used by all loaded chunks and their meshes, in bytes
+    pub fn memory_usage(&self) -> usize {
+        self.memory_used
+    }
+
+    pub fn far_terrain(&self) -> &FarTerrain {
+        &self.far_terrain
+    }
+
+    /// Copies the current frames of the animated textures into the texture, `time` is in seconds
+    pub fn animate_textures(&mut self, time: f32) {
+        let animations = &self.block_manager.atlas.animations;
+        self.animation_frames.resize(animations.len(), None);
+        for (i, a) in animations.iter().enumerate() {
+            let image = a.image(time);
+            if self.animation_frames[i] == Some(image) {
+                continue;
+            }
+            self.animation_frames[i] = Some(image);
+            for (level, img) in a.images[image].iter().enumerate() {
+                self.texture
+                    .update(a.x >> level, a.y >> level, level as u32, img);
+            }
+        }
+    }
+
+    /// Switches to new block types and their texture, and remeshes every chunk which has a mesh
+    ///
+    /// The blocks in the chunks are matched to the new types by name
+    pub fn reload_blocks(&mut self, block_manager: Arc<BlockManager>, texture: Rc<Texture>) {
+        for c in self.chunks.values_mut() {
+            c.remap_blocks(&block_manager);
+        }
+        self.block_manager = block_manager;
+        self.texture = texture;
+        self.animation_frames.clear();
+        self.update_tick_table();
+        self.far_terrain.clear();
+
+        let meshed: Vec<BlockPos> = self
+            .chunks
+            .iter()
+            .filter(|(_, v)| v.mesh.is_some())
+            .map(|(k, _)| *k)
+            .collect();
+        for p in meshed {
+            self.update_chunk(p);
+        }
+    }
+
+    /// Sets what a block does over time, replacing its previous behavior
+    pub fn register_ticks(&mut self, name: &str, behavior: TickBehavior) {
+        self.tick_behaviors.insert(String::from(name), behavior);
+        self.update_tick_table();
+    }
+
+    /// Finds the tick behavior of every block ID
+    fn update_tick_table(&mut self) {
+        self.tick_table = (0..self.block_manager.count() as BlockID)
+            .map(|x| {
+                self.tick_behaviors
+                    .get(&self.block_manager[x].name)
+                    .cloned()
+                    .unwrap_or_default()
+            })
+            .collect();
+    }
+
+    /// Ticks the block at a position after `delay` ticks, loading its chunk if needed
+    pub fn schedule_tick(&mut self, pos: BlockPos, delay: u32) -> Option<()> {
+        let p = BlockPos::new(pos.x >> 4, pos.y >> 4, pos.z >> 4);
+        self.get_block(pos)?;
+        // the scheduled ticks count towards the chunk's memory
+        let mut c = self.take_chunk(p)?;
+        c.schedule_tick(BlockPos::new(pos.x & 15, pos.y & 15, pos.z & 15), delay);
+        self.put_chunk(p, c);
+        Some(())
+    }
+
+    /// Runs the ticks which should have happened until `time` (in seconds since the game
+    /// started), at most a few per call so that a slow frame doesn't make the next one slower
+    pub fn run_ticks(&mut self, time: f32) {
+        const MAX_TICKS: u64 = 10;
+        let target = (time * self.tick_rate) as u64;
+        if target > self.ticks + MAX_TICKS {
+            self.ticks = target - MAX_TICKS;
+        }
+        while self.ticks < target {
+            self.ticks += 1;
+            self.tick();
+        }
+    }
+
+    /// Runs one tick: the scheduled ticks which are due, and the random ticks of the chunks
+    /// which have a mesh
+    fn tick(&mut self) {
+        let mut calls: Vec<(TickFn, BlockPos)> = Vec::new();
+        let mut falls = Vec::new();
+        for (p, c) in self.chunks.iter_mut() {
+            let origin = BlockPos::new(p.x * 16, p.y * 16, p.z * 16);
+            for pos in c.take_due_ticks() {
+                let id = c.get_block(pos).unwrap();
+                if self.block_manager[id].falls {
+                    falls.push(origin + pos);
+                }
+                if let Some(f) = self
+                    .tick_table
+                    .get(id as usize)
+                    .and_then(|x| x.scheduled.clone())
+                {
+                    calls.push((f, origin + pos));
+                }
+            }
+            if c.mesh.is_none() {
+                continue;
+            }
+            for _ in 0..self.random_tick_speed {
+                let pos = BlockPos::new(
+                    self.rng.gen_range(0..16),
+                    self.rng.gen_range(0..16),
+                    self.rng.gen_range(0..16),
+                );
+                let id = c.get_block(pos).unwrap();
+                if let Some(f) = self
+                    .tick_table
+                    .get(id as usize)
+                    .and_then(|x| x.random.clone())
+                {
+                    calls.push((f, origin + pos));
+                }
+            }
+        }
+        for (f, pos) in calls {
+            f(self, pos);
+        }
+        for pos in falls {
+            self.start_falling(pos);
+        }
+    }
+
+    /// Whether the block below a position can hold a falling block
+    fn is_supported(&mut self, pos: BlockPos) -> bool {
+        let blocks = self.block_manager.clone();
+        self.get_block(pos + BlockPos::new(0, -1, 0))
+            .is_none_or(|x| blocks[x].collidable)
+    }
+
+    /// Makes the block at a position fall soon, if it falls and has nothing to stand on
+    fn check_fall(&mut self, pos: BlockPos) {
+        let falls = self
+            .get_block(pos)
+            .is_some_and(|x| self.block_manager[x].falls);
+        if falls && !self.is_supported(pos) {
+            self.schedule_tick(pos, FallingBlock::DELAY);
+        }
+    }
+
+    /// Takes a block out of the world and lets it fall, if it still has nothing to stand on
+    fn start_falling(&mut self, pos: BlockPos) {
+        if self.is_supported(pos) {
+            return;
+        }
+        let (block, state) = match (self.get_block(pos), self.get_state(pos)) {
+            (Some(b), Some(s)) => (b, s),
+            _ => return,
+        };
+        if !self.block_manager[block].falls {
+            return;
+        }
+        self.falling.push(FallingBlock::new(
+            &self.block_manager,
+            pos,
+            block,
+            state,
+            self.mesh_detail(),
+        ));
+        let air = self.block_manager.get("air").unwrap_or(0);
+        self.set_block(pos, air);
+        // the blocks above can fall too
+        self.notify_neighbors(pos);
+    }
+
+    /// Moves the falling blocks, the ones which hit the ground become blocks again
+    pub fn update_falling_blocks(&mut self) {
+        let mut falling = std::mem::take(&mut self.falling);
+        falling.retain_mut(|x| match x.update(self) {
+            Some(pos) => {
+                self.land(pos, x.block, x.state);
+                false
+            }
+            None => true,
+        });
+        // blocks which started falling while the others were updated
+        falling.append(&mut self.falling);
+        self.falling = falling;
+    }
+
+    /// Puts a falling block into the first space at or above `pos` which isn't taken by a
+    /// collidable block, a block which isn't collidable gets broken
+    fn land(&mut self, pos: BlockPos, block: BlockID, state: BlockState) {
+        let blocks = self.block_manager.clone();
+        let air = blocks.get("air").unwrap_or(0);
+        let mut pos = pos;
+        while self.get_block(pos).is_some_and(|x| blocks[x].collidable) {
+            pos.y += 1;
+        }
+        if self.get_block(pos).is_some_and(|x| x != air) {
+            self.break_block(pos);
+        }
+        self.set_block_state(pos, block, state);
+        self.notify_neighbors(pos);
+        self.check_fall(pos);
+    }
+
+    /// Puts the falling blocks which are inside the chunks about to be unloaded back into the world
+    /// where they are, so they are saved with the chunk. They continue falling once it's loaded
+    /// again.
+    fn place_falling_blocks(&mut self, unloaded: impl Fn(BlockPos) -> bool) {
+        let (placed, falling): (Vec<FallingBlock>, _) = std::mem::take(&mut self.falling)
+            .into_iter()
+            .partition(|x| unloaded(Self::chunk_at(x.pos)));
+        self.falling = falling;
+        for x in placed {
+            let pos = BlockPos::new(
+                x.pos.x.floor() as _,
+                x.pos.y.floor() as _,
+                x.pos.z.floor() as _,
+            );
+            self.land(pos, x.block, x.state);
+        }
+    }
+
+    /// Renders the falling blocks, every one with its own model matrix
+    pub fn render_falling_blocks(&self, view: &mut RenderView) {
+        self.texture.bind();
+        for x in &self.falling {
+            view.set_model(x.model_matrix());
+            x.render();
+        }
+        view.set_model(Mat4::IDENTITY);
+    }
+
     /// Render everything, don't wait for chunks to generate
-    pub fn render(&self) {
+    ///
+    /// Opaque meshes are drawn first, then the cutout ones, then the translucent ones from back to
+    /// front. Returns how many chunks were drawn and how many have a mesh.
+    pub fn render(&mut self, view: &mut RenderView, frustum: &util::Frustum) -> (usize, usize) {
+        let camera = frustum.camera();
+        if let Some(chunk) = self.chunks.get_mut(&Self::chunk_at(camera)) {
+            chunk.sort_translucent(&self.arena, camera);
+        }
+
         self.texture.bind();
-        for (_, v) in self.chunks.iter() {
-            v.render();
+        let total = self
+            .chunks
+            .values()
+            .filter(|x| x.mesh.as_ref().is_some_and(|x| !x.is_empty()))
+            .count();
+        let mut visible: Vec<&Chunk> = self
+            .visible_chunks(frustum)
+            .into_iter()
+            .filter(|x| x.mesh.as_ref().is_some_and(|x| !x.is_empty()))
+            .collect();
+        let drawn = visible.len();
+        fn layer<'a>(chunks: &[&'a Chunk], layer: RenderLayer) -> Vec<&'a ArenaMesh> {
+            chunks
+                .iter()
+                .filter_map(|x| x.mesh.as_ref())
+                .map(|x| x.layer(layer))
+                .collect()
         }
+
+        view.set_render_layer(RenderLayer::Opaque);
+        self.arena
+            .draw(layer(&visible, RenderLayer::Opaque).into_iter(), |x| {
+                view.set_model(x)
+            });
+        self.far_terrain.draw(&self.arena, view, frustum);
+
+        view.set_render_layer(RenderLayer::Cutout);
+        self.arena
+            .draw(layer(&visible, RenderLayer::Cutout).into_iter(), |x| {
+                view.set_model(x)
+            });
+
+        // translucent faces don't hide what's behind them, which is drawn first
+        let distance = |x: &Chunk| (x.origin() + 8.0).distance_squared(camera);
+        visible.sort_by(|a, b| distance(b).total_cmp(&distance(a)));
+        view.set_render_layer(RenderLayer::Translucent);
+        unsafe {
+            glDepthMask(false as _);
+        }
+        self.arena
+            .draw_in_order(layer(&visible, RenderLayer::Translucent).into_iter(), |x| {
+                view.set_model(x)
+            });
+        unsafe {
+            glDepthMask(true as _);
+        }
+
+        view.set_render_layer(RenderLayer::Opaque);
+        view.set_model(Mat4::IDENTITY);
+        (drawn, total)
+    }
+
+    /// The position of the chunk which a position is in
+    fn chunk_at(pos: Vec3) -> BlockPos {
+        BlockPos::new(
+            (pos.x / 16.0).floor() as i32,
+            (pos.y / 16.0).floor() as i32,
+            (pos.z / 16.0).floor() as i32,
+        )
+    }
+
+    /// The chunks which might be seen from the camera
+    ///
+    /// Searches outwards from the chunk with the camera, through the frustum. A chunk is only
+    /// entered if it can be seen from the side that the search came from through the previous
+    /// chunk, and the search never turns back in a direction it has already moved away from.
+    ///
+    /// If the chunk with the camera isn't loaded (e.g. above the world), every chunk in the
+    /// frustum is returned instead.
+    fn visible_chunks(&self, frustum: &util::Frustum) -> Vec<&Chunk> {
+        let start = Self::chunk_at(frustum.camera());
+        let start_chunk = match self.chunks.get(&start) {
+            Some(x) => x,
+            None => {
+                return self
+                    .chunks
+                    .values()
+                    .filter(|x| x.is_visible(frustum))
+                    .map(|x| x.as_ref())
+                    .collect()
+            }
+        };
+        let mut chunks = Vec::new();
+        let mut visited = HashSet::from([start]);
+        // the chunk, the side it was entered from, and every direction moved in to get there
+        let mut queue: VecDeque<(&Chunk, Option<BlockSide>, Vec<BlockSide>)> =
+            VecDeque::from([(start_chunk.as_ref(), None, Vec::new())]);
+        while let Some((chunk, from, directions)) = queue.pop_front() {
+            chunks.push(chunk);
+            for side in BlockSide::ALL {
+                if directions.contains(&-side)
+                    || from.is_some_and(|x| !chunk.connections.connects(x, side))
+                {
+                    continue;
+                }
+                let pos = chunk.pos + side;
+                let next = match self.chunks.get(&pos) {
+                    Some(x) if x.is_visible(frustum) => x,
+                    _ => continue,
+                };
+                if visited.insert(pos) {
+                    let mut directions = directions.clone();
+                    if !directions.contains(&side) {
+                        directions.push(side);
+                    }
+                    queue.push_back((next, Some(-side), directions));
+                }
+            }
+        }
+        chunks
     }
 
     /// Takes a chunk out of the hash map, updates it, and puts it back in
     fn update_chunk(&mut self, p: BlockPos) -> Option<()> {
-        let mut c = match self.chunks.remove(&p) {
+        let mut c = match self.take_chunk(p) {
             Some(x) => x,
             None => {
                 self.cache_chunk(p);
-                self.chunks.remove(&p)?
+                self.take_chunk(p)?
             }
         };
         let bm = self.block_manager.clone();
         c.update(bm, self);
-        self.chunks.insert(p, c);
+        c.last_used = self.frame;
+        self.put_chunk(p, c);
         Some(())
     }
 
@@ -145,59 +685,170 @@ impl ChunkServer {
     ///
     /// The chunk will either get overwritten by a generated one or deleted later.
     fn cache_chunk(&mut self, pos: BlockPos) {
-        self.chunks.insert(
-            pos,
-            Box::new(Chunk::new(pos, &self.block_manager, &self.save_dir)),
-        );
+        let mut c = Box::new(Chunk::new(pos, &self.block_manager, &self.save_dir));
+        c.last_used = self.frame;
+        self.put_chunk(pos, c);
     }
 
     /// Get a block
     pub fn get_block(&mut self, pos: BlockPos) -> Option<BlockID> {
         let p = BlockPos::new(pos.x >> 4, pos.y >> 4, pos.z >> 4);
-        match self.chunks.get(&p) {
+        let c = match self.chunks.get_mut(&p) {
             Some(x) => x,
             None => {
                 self.cache_chunk(p);
-                self.chunks.get(&p)?
+                self.chunks.get_mut(&p)?
+            }
+        };
+        c.last_used = self.frame;
+        c.get_block(BlockPos::new(pos.x & 15, pos.y & 15, pos.z & 15))
+    }
+
+    /// Get a block without loading its chunk, None if the chunk isn't loaded
+    pub fn loaded_block(&self, pos: BlockPos) -> Option<BlockID> {
+        let p = BlockPos::new(pos.x >> 4, pos.y >> 4, pos.z >> 4);
+        self.chunks
+            .get(&p)?
+            .get_block(BlockPos::new(pos.x & 15, pos.y & 15, pos.z & 15))
+    }
+
+    /// Get the state of a block
+    pub fn get_state(&mut self, pos: BlockPos) -> Option<BlockState> {
+        let p = BlockPos::new(pos.x >> 4, pos.y >> 4, pos.z >> 4);
+        self.get_block(pos)?;
+        self.chunks[&p].get_state(BlockPos::new(pos.x & 15, pos.y & 15, pos.z & 15))
+    }
+
+    /// Places a block like the player does, if its behavior allows it, and notifies the
+    /// neighbors
+    ///
+    /// Returns whether the block was placed
+    pub fn place_block(&mut self, pos: BlockPos, block: BlockID, state: BlockState) -> bool {
+        let behavior = self.block_manager.behavior(block);
+        if let Some(b) = &behavior {
+            if !b.can_place_at(self, pos, state) {
+                return false;
+            }
+        }
+        if self.set_block_state(pos, block, state).is_none() {
+            return false;
+        }
+        if let Some(b) = &behavior {
+            b.on_place(self, pos, state);
+        }
+        self.notify_neighbors(pos);
+        self.check_fall(pos);
+        true
+    }
+
+    /// Replaces a block with air like the player does, and notifies the neighbors
+    pub fn break_block(&mut self, pos: BlockPos) -> Option<()> {
+        let block = self.get_block(pos)?;
+        let state = self.get_state(pos)?;
+        let air = self.block_manager.get("air").unwrap_or(0);
+        self.set_block(pos, air)?;
+        if let Some(b) = self.block_manager.behavior(block) {
+            b.on_break(self, pos, state);
+        }
+        self.notify_neighbors(pos);
+        Some(())
+    }
+
+    /// Uses (left-clicks) a block, returns whether its behavior did anything
+    pub fn use_block(&mut self, pos: BlockPos) -> bool {
+        let (block, state) = match (self.get_block(pos), self.get_state(pos)) {
+            (Some(b), Some(s)) => (b, s),
+            _ => return false,
+        };
+        match self.block_manager.behavior(block) {
+            Some(b) => b.on_use(self, pos, state),
+            None => false,
+        }
+    }
+
+    /// Tells the blocks next to a position that it has changed, the falling blocks among them
+    /// start falling if they lost their support
+    pub fn notify_neighbors(&mut self, pos: BlockPos) {
+        for side in BlockSide::ALL {
+            let neighbor = pos + side;
+            self.check_fall(neighbor);
+            let behavior = self
+                .get_block(neighbor)
+                .and_then(|x| self.block_manager.behavior(x));
+            if let Some(b) = behavior {
+                b.on_neighbor_changed(self, neighbor, pos);
             }
         }
-        .get_block(BlockPos::new(pos.x & 15, pos.y & 15, pos.z & 15))
     }
 
-    /// Set a block
+    /// Set a block in its default state
     pub fn set_block(&mut self, pos: BlockPos, block: BlockID) -> Option<()> {
+        self.set_block_state(pos, block, 0)
+    }
+
+    /// Set a block and its state
+    pub fn set_block_state(
+        &mut self,
+        pos: BlockPos,
+        block: BlockID,
+        state: BlockState,
+    ) -> Option<()> {
         let p = BlockPos::new(pos.x >> 4, pos.y >> 4, pos.z >> 4);
         let inner_pos = BlockPos::new(pos.x & 15, pos.y & 15, pos.z & 15);
         // we need to take the chunk out, update it, and put it back in 🤷.
-        let mut c = match self.chunks.remove(&p) {
+        let mut c = match self.take_chunk(p) {
             Some(x) => x,
             None => {
                 self.cache_chunk(p);
-                self.chunks.remove(&p)?
+                self.take_chunk(p)?
             }
         };
-        c.set_block(inner_pos, block);
+        c.set_block(inner_pos, block, state);
         let bm = self.block_manager.clone();
         c.update(bm, self);
-        self.chunks.insert(p, c);
+        c.last_used = self.frame;
+        self.put_chunk(p, c);
 
-        // Update the neighboring chunks, if it's needed
-        if inner_pos.x == 0 {
-            self.update_chunk(p + BlockPos::new(-1, 0, 0))?;
-        } else if inner_pos.x == 15 {
-            self.update_chunk(p + BlockPos::new(1, 0, 0))?;
-        }
-        if inner_pos.y == 0 {
-            self.update_chunk(p + BlockPos::new(0, -1, 0))?;
-        } else if inner_pos.y == 15 {
-            self.update_chunk(p + BlockPos::new(0, 1, 0))?;
-        }
-        if inner_pos.z == 0 {
-            self.update_chunk(p + BlockPos::new(0, 0, -1))?;
-        } else if inner_pos.z == 15 {
-            self.update_chunk(p + BlockPos::new(0, 0, 1))?;
+        // Update the neighboring chunks which touch the block, if it's needed. The ones touching
+        // only an edge or a corner are included, their ambient occlusion can change too.
+        let offsets = |x: i32| match x {
+            0 => vec![0, -1],
+            15 => vec![0, 1],
+            _ => vec![0],
+        };
+        for &i in &offsets(inner_pos.x) {
+            for &j in &offsets(inner_pos.y) {
+                for &k in &offsets(inner_pos.z) {
+                    if (i, j, k) != (0, 0, 0) {
+                        self.update_chunk(p + BlockPos::new(i, j, k))?;
+                    }
+                }
+            }
         }
 
         Some(())
     }
 }
+
+#[cfg(test)]
+impl ChunkServer {
+    /// A world with the blocks of blocks.json and the fake GL functions, which saves into an empty
+    /// directory. The random ticks are seeded so they can be repeated.
+    pub fn for_tests(name: &str, seed: u64) -> Self {
+        crate::graphics::fake_gl::load();
+        let blocks = Arc::new(BlockManager::from_files(&[String::from("blocks.json")]).unwrap());
+        let dir = std::env::temp_dir().join(format!("unsafe-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let config = json::object! {
+            save_dir: dir.to_str().unwrap(),
+            view_distance: 2,
+            loading_limit: 1,
+            far_terrain_distance: 0,
+        };
+        let texture = Rc::new(Texture::from_image(&blocks.atlas.image, 0));
+        let mut world = Self::new(texture, blocks, &config);
+        tick::register_defaults(&mut world);
+        world.rng = StdRng::seed_from_u64(seed);
+        world
+    }
+}
@@ -0,0 +1,210 @@
+use super::*;
+use rand::Rng;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+
+/// Called with the position of the ticked block
+pub type TickFn = Arc<dyn Fn(&mut ChunkServer, BlockPos) + Sync + Send>;
+
+/// What a block does over time, registered with `ChunkServer::register_ticks`
+#[derive(Clone, Default)]
+pub struct TickBehavior {
+    /// Called for randomly chosen blocks of every loaded chunk
+    pub random: Option<TickFn>,
+    /// Called when a tick scheduled with `ChunkServer::schedule_tick` is due
+    pub scheduled: Option<TickFn>,
+}
+
+/// Registers the behaviors of the built-in blocks
+pub fn register_defaults(world: &mut ChunkServer) {
+    world.register_ticks(
+        "grass",
+        TickBehavior {
+            random: Some(Arc::new(grass_tick)),
+            scheduled: None,
+        },
+    );
+    world.register_ticks(
+        "leaves",
+        TickBehavior {
+            random: Some(Arc::new(leaves_tick)),
+            scheduled: Some(Arc::new(leaves_tick)),
+        },
+    );
+}
+
+/// Whether the block at a position is covered by an opaque block
+fn is_covered(world: &mut ChunkServer, pos: BlockPos) -> bool {
+    let blocks = world.block_manager.clone();
+    world
+        .get_block(pos + BlockPos::new(0, 1, 0))
+        .is_some_and(|x| blocks[x].opaque)
+}
+
+/// Grass dies when it gets covered, otherwise it spreads to nearby dirt
+fn grass_tick(world: &mut ChunkServer, pos: BlockPos) {
+    let blocks = world.block_manager.clone();
+    let (grass, dirt) = match (blocks.get("grass"), blocks.get("dirt")) {
+        (Some(g), Some(d)) => (g, d),
+        _ => return,
+    };
+    if is_covered(world, pos) {
+        world.set_block(pos, dirt);
+        return;
+    }
+    let target = pos
+        + BlockPos::new(
+            world.rng.gen_range(-1..=1),
+            world.rng.gen_range(-1..=1),
+            world.rng.gen_range(-1..=1),
+        );
+    if world.get_block(target) == Some(dirt) && !is_covered(world, target) {
+        world.set_block(target, grass);
+    }
+}
+
+/// Leaves which aren't connected to a log through at most `DISTANCE` other leaves disappear, and
+/// the leaves next to them get checked soon after
+///
+/// Leaves placed by a player are `persistent` and never disappear. Neither do any leaves if there
+/// are no logs, or if the search reaches a chunk which isn't loaded.
+fn leaves_tick(world: &mut ChunkServer, pos: BlockPos) {
+    const DISTANCE: u32 = 4;
+    let blocks = world.block_manager.clone();
+    let (log, leaves) = match (blocks.get("log"), blocks.get("leaves")) {
+        (Some(log), Some(leaves)) => (log, leaves),
+        _ => return,
+    };
+    if world.loaded_block(pos) != Some(leaves) {
+        return;
+    }
+    let state = world.get_state(pos).unwrap_or(0);
+    if blocks[leaves].states.get(state, "persistent") == Some("true") {
+        return;
+    }
+    // search through the leaves around the block
+    let mut visited = HashSet::from([pos]);
+    let mut queue = VecDeque::from([(pos, 0)]);
+    while let Some((p, distance)) = queue.pop_front() {
+        for side in util::BlockSide::ALL {
+            let n = p + side;
+            if !visited.insert(n) {
+                continue;
+            }
+            match world.loaded_block(n) {
+                None => return,
+                Some(x) if x == log => return,
+                Some(x) if x == leaves && distance + 1 < DISTANCE => {
+                    queue.push_back((n, distance + 1))
+                }
+                _ => (),
+            }
+        }
+    }
+    world.set_block(pos, blocks.get("air").unwrap_or(0));
+    for side in util::BlockSide::ALL {
+        let neighbor = pos + side;
+        if world.loaded_block(neighbor) == Some(leaves) {
+            let delay = world.rng.gen_range(2..20);
+            world.schedule_tick(neighbor, delay);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The blocks are placed high above the terrain, in the middle of a chunk
+    const GROUND: BlockPos = BlockPos { x: 8, y: 104, z: 8 };
+
+    fn id(world: &ChunkServer, name: &str) -> BlockID {
+        world.block_manager.get(name).unwrap()
+    }
+
+    fn place(world: &mut ChunkServer, offset: BlockPos, name: &str) {
+        let block = id(world, name);
+        world.set_block(GROUND + offset, block).unwrap();
+    }
+
+    fn block_at(world: &mut ChunkServer, offset: BlockPos) -> String {
+        let block = world.get_block(GROUND + offset).unwrap();
+        world.block_manager[block].name.clone()
+    }
+
+    /// Runs `n` times 10 ticks, the most which `run_ticks` does at once, at 20 ticks a second
+    fn run_ticks(world: &mut ChunkServer, n: u32) {
+        for i in 1..=n {
+            world.run_ticks(i as f32 * 0.5);
+        }
+    }
+
+    #[test]
+    fn covered_grass_dies() {
+        let mut world = ChunkServer::for_tests("grass-dies", 1);
+        place(&mut world, BlockPos::new(0, 0, 0), "grass");
+        place(&mut world, BlockPos::new(0, 1, 0), "stone");
+        grass_tick(&mut world, GROUND);
+        assert_eq!(block_at(&mut world, BlockPos::new(0, 0, 0)), "dirt");
+    }
+
+    #[test]
+    fn grass_spreads_to_uncovered_dirt() {
+        for seed in 0..4 {
+            let mut world = ChunkServer::for_tests(&format!("grass-spreads-{}", seed), seed);
+            place(&mut world, BlockPos::new(0, 0, 0), "grass");
+            place(&mut world, BlockPos::new(1, 0, 0), "dirt");
+            place(&mut world, BlockPos::new(-1, -1, 0), "dirt");
+            place(&mut world, BlockPos::new(0, 0, 1), "dirt");
+            place(&mut world, BlockPos::new(0, 1, 1), "planks");
+            for _ in 0..200 {
+                grass_tick(&mut world, GROUND);
+            }
+            assert_eq!(block_at(&mut world, BlockPos::new(1, 0, 0)), "grass");
+            assert_eq!(block_at(&mut world, BlockPos::new(-1, -1, 0)), "grass");
+            assert_eq!(block_at(&mut world, BlockPos::new(0, 0, 1)), "dirt");
+        }
+    }
+
+    #[test]
+    fn leaves_without_a_log_decay() {
+        let mut world = ChunkServer::for_tests("leaves-decay", 2);
+        for x in 0..3 {
+            place(&mut world, BlockPos::new(x, 0, 0), "leaves");
+        }
+        leaves_tick(&mut world, GROUND);
+        assert_eq!(block_at(&mut world, BlockPos::new(0, 0, 0)), "air");
+        assert_eq!(block_at(&mut world, BlockPos::new(1, 0, 0)), "leaves");
+        // the neighbors are checked by scheduled ticks, which take the others with them
+        run_ticks(&mut world, 8);
+        for x in 1..3 {
+            assert_eq!(block_at(&mut world, BlockPos::new(x, 0, 0)), "air");
+        }
+    }
+
+    #[test]
+    fn leaves_near_a_log_stay() {
+        let mut world = ChunkServer::for_tests("leaves-stay", 3);
+        place(&mut world, BlockPos::new(0, 0, 0), "log");
+        for x in 1..=4 {
+            place(&mut world, BlockPos::new(x, 0, 0), "leaves");
+        }
+        place(&mut world, BlockPos::new(0, 0, 5), "leaves");
+        let leaves = id(&world, "leaves");
+        let persistent = world.block_manager[leaves]
+            .states
+            .set(0, "persistent", "true");
+        world
+            .set_block_state(GROUND + BlockPos::new(5, 0, 0), leaves, persistent)
+            .unwrap();
+        for x in 1..=5 {
+            leaves_tick(&mut world, GROUND + BlockPos::new(x, 0, 0));
+        }
+        leaves_tick(&mut world, GROUND + BlockPos::new(0, 0, 5));
+        for x in 1..=5 {
+            assert_eq!(block_at(&mut world, BlockPos::new(x, 0, 0)), "leaves");
+        }
+        // not connected to the log
+        assert_eq!(block_at(&mut world, BlockPos::new(0, 0, 5)), "air");
+    }
+}
@@ -0,0 +1,116 @@
+use super::*;
+use util::BlockSide;
+
+/// Which sides of a chunk can be seen from which other sides, through the blocks that aren't
+/// solid
+///
+/// Used for cave culling: chunks that can only be reached by looking through solid blocks aren't
+/// drawn.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Connections(u64);
+
+impl Connections {
+    /// Every side can be seen from every other side, like in a chunk full of air
+    pub const ALL: Self = Self(u64::MAX);
+
+    fn bit(a: BlockSide, b: BlockSide) -> u64 {
+        1 << (index(a) * 6 + index(b))
+    }
+
+    fn connect(&mut self, a: BlockSide, b: BlockSide) {
+        self.0 |= Self::bit(a, b) | Self::bit(b, a);
+    }
+
+    /// Whether the side `b` can be seen when looking in through the side `a`
+    pub fn connects(&self, a: BlockSide, b: BlockSide) -> bool {
+        self.0 & Self::bit(a, b) != 0
+    }
+
+    /// Flood fills the blocks which aren't solid and connects the sides which every group of
+    /// them touches
+    pub fn find(solid: impl Fn(usize, usize, usize) -> bool) -> Self {
+        let mut connections = Self(0);
+        let mut visited = [[[false; 16]; 16]; 16];
+        let mut stack = Vec::new();
+        for i in 0..16 {
+            for j in 0..16 {
+                for k in 0..16 {
+                    if visited[i][j][k] || solid(i, j, k) {
+                        continue;
+                    }
+                    visited[i][j][k] = true;
+                    stack.push((i, j, k));
+                    let mut touched = Vec::new();
+                    while let Some((x, y, z)) = stack.pop() {
+                        for side in BlockSide::ALL {
+                            let n = BlockPos::new(x as i32, y as i32, z as i32) + side;
+                            if !n.is_in_chunk() {
+                                if !touched.contains(&side) {
+                                    touched.push(side);
+                                }
+                                continue;
+                            }
+                            let (x, y, z) = (n.x as usize, n.y as usize, n.z as usize);
+                            if !visited[x][y][z] && !solid(x, y, z) {
+                                visited[x][y][z] = true;
+                                stack.push((x, y, z));
+                            }
+                        }
+                    }
+                    for &a in &touched {
+                        for &b in &touched {
+                            connections.connect(a, b);
+                        }
+                    }
+                }
+            }
+        }
+        connections
+    }
+}
+
+fn index(side: BlockSide) -> usize {
+    BlockSide::ALL.iter().position(|&x| x == side).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_chunk_connects_everything() {
+        let c = Connections::find(|_, _, _| false);
+        for a in BlockSide::ALL {
+            for b in BlockSide::ALL {
+                assert!(c.connects(a, b));
+            }
+        }
+    }
+
+    #[test]
+    fn solid_chunk_connects_nothing() {
+        let c = Connections::find(|_, _, _| true);
+        assert_eq!(c, Connections(0));
+    }
+
+    #[test]
+    fn wall_splits_the_chunk() {
+        // a solid layer at y = 8 separates the top from the bottom
+        let c = Connections::find(|_, y, _| y == 8);
+        assert!(c.connects(BlockSide::Top, BlockSide::Left));
+        assert!(c.connects(BlockSide::Bottom, BlockSide::Front));
+        assert!(c.connects(BlockSide::Left, BlockSide::Right));
+        assert!(!c.connects(BlockSide::Top, BlockSide::Bottom));
+        assert!(!c.connects(BlockSide::Bottom, BlockSide::Top));
+    }
+
+    #[test]
+    fn tunnel_connects_only_its_ends() {
+        // an open tunnel along x through solid stone
+        let c = Connections::find(|_, y, z| !(y == 4 && z == 4));
+        assert!(c.connects(BlockSide::Left, BlockSide::Right));
+        assert!(c.connects(BlockSide::Right, BlockSide::Left));
+        assert!(!c.connects(BlockSide::Left, BlockSide::Top));
+        assert!(!c.connects(BlockSide::Front, BlockSide::Back));
+    }
+}
@@ -0,0 +1,618 @@
+use super::*;
+use model::attribute;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// A vertex of the meshes in a `MeshArena`
+///
+/// 28 bytes instead of the 64 bytes of the separate buffers of a `Model`:
+///
+/// | bytes | field      |
+/// |-------|------------|
+/// | 0-7   | `tile`     |
+/// | 8-11  | `texcoord` |
+/// | 12-14 | `normal`   |
+/// | 15    | `ao`       |
+/// | 16-18 | `color`    |
+/// | 19    | `face`     |
+/// | 20-25 | `pos`      |
+/// | 26-27 | padding    |
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct CompactVertex {
+    /// The repeated tile of the atlas, see `MeshData::tiles`
+    tile: [u16; 4],
+    texcoord: [u16; 2],
+    /// The normal of vertices which don't have a `face`, e.g. of leaves or cross models
+    normal: [i8; 3],
+    ao: u8,
+    color: [u8; 3],
+    /// The lowest 3 bits are the side the normal points to: 1 + its index in `FACE_NORMALS`, 0
+    /// if the normal is in `normal`. The shader has the same table.
+    face: u8,
+    /// Position relative to the origin of the mesh, see `MeshArena::SCALE`
+    pos: [u16; 3],
+    _padding: u16,
+}
+
+/// The normals of the faces of a block: top, bottom, left, right, front and back
+const FACE_NORMALS: [[f32; 3]; 6] = [
+    [0.0, 1.0, 0.0],
+    [0.0, -1.0, 0.0],
+    [-1.0, 0.0, 0.0],
+    [1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0],
+    [0.0, 0.0, -1.0],
+];
+
+impl CompactVertex {
+    /// Packs the vertex `i` of a mesh. Texture coordinates have to be between 0 and 1, colors are
+    /// clamped to 0 to 1.
+    fn pack(data: &MeshData, i: usize, origin: Vec3) -> Self {
+        let v = data.vertices[i];
+        let pos = (Vec3::new(v.0, v.1, v.2) - origin + MeshArena::OFFSET) * MeshArena::SCALE;
+        let pos = pos.round().clamp(Vec3::ZERO, Vec3::splat(u16::MAX as f32));
+        let t = data.texcoords[i];
+        let n = data.normals[i];
+        let c = data.colors.get(i).copied().unwrap_or((1.0, 1.0, 1.0));
+        let tile = data.tiles.get(i).copied().unwrap_or_default();
+        // values from 0 to 1 (or -1 to 1) use the whole range of the integer
+        let snorm = |x: f32| (x.clamp(-1.0, 1.0) * 127.0).round() as i8;
+        let short = |x: f32| (x.clamp(0.0, 1.0) * 65535.0).round() as u16;
+        let byte = |x: f32| (x.clamp(0.0, 1.0) * 255.0).round() as u8;
+        // normals along an axis only need the index of the face
+        let n = Vec3::new(n.0, n.1, n.2).normalize_or_zero();
+        let (face, normal) = match FACE_NORMALS
+            .iter()
+            .position(|&x| Vec3::from(x).dot(n) > 0.9999)
+        {
+            Some(i) => (i as u8 + 1, [0; 3]),
+            None => (0, [snorm(n.x), snorm(n.y), snorm(n.z)]),
+        };
+        CompactVertex {
+            tile: [short(tile.0), short(tile.1), short(tile.2), short(tile.3)],
+            texcoord: [short(t.0), short(t.1)],
+            normal,
+            ao: byte(data.ao.get(i).copied().unwrap_or(1.0)),
+            color: [byte(c.0), byte(c.1), byte(c.2)],
+            face,
+            pos: [pos.x as u16, pos.y as u16, pos.z as u16],
+            _padding: 0,
+        }
+    }
+}
+
+/// Keeps track of the free parts of a buffer, free ranges next to each other are merged
+struct RangeAllocator {
+    capacity: usize,
+    /// Start and length of every free range, sorted by the start
+    free: Vec<(usize, usize)>,
+}
+
+impl RangeAllocator {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            free: vec![(0, capacity)],
+        }
+    }
+
+    /// Finds a range of `len` starting at a multiple of `align`, in the smallest free range where
+    /// it fits so the large ones stay available
+    fn alloc(&mut self, len: usize, align: usize) -> Option<usize> {
+        let fits =
+            |&(start, size): &(usize, usize)| start.next_multiple_of(align) + len <= start + size;
+        let (i, &(start, size)) = self
+            .free
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| fits(x))
+            .min_by_key(|(_, x)| x.1)?;
+        let aligned = start.next_multiple_of(align);
+        let mut rest = Vec::new();
+        if aligned > start {
+            rest.push((start, aligned - start));
+        }
+        if aligned + len < start + size {
+            rest.push((aligned + len, start + size - aligned - len));
+        }
+        self.free.splice(i..i + 1, rest);
+        Some(aligned)
+    }
+
+    fn free(&mut self, start: usize, len: usize) {
+        let i = self.free.partition_point(|x| x.0 < start);
+        self.free.insert(i, (start, len));
+        // merge with the next range, then with the previous one
+        if i + 1 < self.free.len() && start + len == self.free[i + 1].0 {
+            self.free[i].1 += self.free[i + 1].1;
+            self.free.remove(i + 1);
+        }
+        if i > 0 && self.free[i - 1].0 + self.free[i - 1].1 == start {
+            self.free[i - 1].1 += self.free[i].1;
+            self.free.remove(i);
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.free == [(0, self.capacity)]
+    }
+}
+
+/// One vertex and one index buffer which many meshes are stored in, with a VAO for drawing them
+struct Page {
+    vao: u32,
+    vertex_buffer: u32,
+    index_buffer: u32,
+    /// In vertices
+    vertices: RangeAllocator,
+    /// In bytes
+    indices: RangeAllocator,
+}
+
+impl Drop for Page {
+    fn drop(&mut self) {
+        unsafe {
+            glDeleteBuffers(1, &self.vertex_buffer);
+            glDeleteBuffers(1, &self.index_buffer);
+            glDeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
+
+impl Page {
+    fn new(vertex_capacity: usize, index_capacity: usize) -> Self {
+        let mut vao = 0;
+        let mut buffers = [0; 2];
+        let stride = std::mem::size_of::<CompactVertex>();
+        unsafe {
+            glGenVertexArrays(1, &mut vao);
+            assert_ne!(vao, 0);
+            glBindVertexArray(vao);
+            glGenBuffers(2, buffers.as_mut_ptr());
+            assert!(buffers[0] != 0 && buffers[1] != 0);
+
+            glBindBuffer(gl33::GL_ARRAY_BUFFER, buffers[0]);
+            glBufferData(
+                gl33::GL_ARRAY_BUFFER,
+                (vertex_capacity * stride) as isize,
+                std::ptr::null(),
+                gl33::GL_DYNAMIC_DRAW,
+            );
+            attribute(0, 3, gl33::GL_UNSIGNED_SHORT, false, stride, 20);
+            attribute(1, 2, gl33::GL_UNSIGNED_SHORT, true, stride, 8);
+            attribute(2, 3, gl33::GL_BYTE, true, stride, 12);
+            attribute(3, 3, gl33::GL_UNSIGNED_BYTE, true, stride, 16);
+            attribute(4, 4, gl33::GL_UNSIGNED_SHORT, true, stride, 0);
+            attribute(5, 1, gl33::GL_UNSIGNED_BYTE, true, stride, 15);
+            // not normalized, so the shader gets the index
+            attribute(6, 1, gl33::GL_UNSIGNED_BYTE, false, stride, 19);
+
+            glBindBuffer(gl33::GL_ELEMENT_ARRAY_BUFFER, buffers[1]);
+            glBufferData(
+                gl33::GL_ELEMENT_ARRAY_BUFFER,
+                index_capacity as isize,
+                std::ptr::null(),
+                gl33::GL_DYNAMIC_DRAW,
+            );
+            glBindVertexArray(0);
+        }
+        Self {
+            vao,
+            vertex_buffer: buffers[0],
+            index_buffer: buffers[1],
+            vertices: RangeAllocator::new(vertex_capacity),
+            indices: RangeAllocator::new(index_capacity),
+        }
+    }
+
+    /// Reserves space for a mesh, returns the first vertex and the offset of the indices
+    fn alloc(&mut self, vertices: usize, index_bytes: usize) -> Option<(usize, usize)> {
+        let first_vertex = self.vertices.alloc(vertices, 1)?;
+        match self.indices.alloc(index_bytes, 4) {
+            Some(offset) => Some((first_vertex, offset)),
+            None => {
+                self.vertices.free(first_vertex, vertices);
+                None
+            }
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        self.vertices.capacity * std::mem::size_of::<CompactVertex>() + self.indices.capacity
+    }
+}
+
+/// Stores the meshes of the chunks in a few large buffers, so drawing them only switches buffers
+/// a couple of times instead of for every chunk
+///
+/// Meshes are packed into `CompactVertex`es and use 16 bit indices if possible. A new buffer is
+/// added when the old ones are full, and removed again once it's empty.
+#[derive(Clone)]
+pub struct MeshArena {
+    pages: Rc<RefCell<Vec<Option<Page>>>>,
+}
+
+/// A mesh in a `MeshArena`, the space is freed when it's dropped
+pub struct ArenaMesh {
+    arena: MeshArena,
+    /// None if the mesh is empty
+    page: Option<usize>,
+    origin: Vec3,
+    /// How many blocks one unit of the mesh is
+    scale: f32,
+    first_vertex: usize,
+    vertex_count: usize,
+    /// In bytes
+    index_offset: usize,
+    index_bytes: usize,
+    count: i32,
+    /// `GL_UNSIGNED_INT` or `GL_UNSIGNED_SHORT`
+    index_type: gl33::GLenum,
+}
+
+impl Drop for ArenaMesh {
+    fn drop(&mut self) {
+        let page_index = match self.page {
+            Some(x) => x,
+            None => return,
+        };
+        let mut pages = self.arena.pages.borrow_mut();
+        let page = pages[page_index].as_mut().unwrap();
+        page.vertices.free(self.first_vertex, self.vertex_count);
+        page.indices.free(self.index_offset, self.index_bytes);
+        // keep one buffer around, even if it's empty
+        let empty = page.vertices.is_empty() && page.indices.is_empty();
+        if empty && pages.iter().flatten().count() > 1 {
+            pages[page_index] = None;
+        }
+    }
+}
+
+impl ArenaMesh {
+    /// Whether there is nothing to draw
+    pub fn is_empty(&self) -> bool {
+        self.page.is_none()
+    }
+
+    /// How many bytes the mesh takes up on the GPU
+    pub fn memory_usage(&self) -> usize {
+        self.vertex_count * std::mem::size_of::<CompactVertex>() + self.index_bytes
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.vertex_count
+    }
+
+    /// The model matrix, which turns the packed positions back into world positions
+    fn model(&self) -> Mat4 {
+        Mat4::from_scale(Vec3::splat(self.scale))
+            * Mat4::from_translation(self.origin - MeshArena::OFFSET)
+            * Mat4::from_scale(Vec3::splat(1.0 / MeshArena::SCALE))
+    }
+}
+
+impl MeshArena {
+    /// How many steps a block is divided into by the positions of the vertices
+    pub const SCALE: f32 = 2048.0;
+    /// How far the vertices of a mesh may be before its origin, in blocks. They can be up to
+    /// `65536 / SCALE - OFFSET` blocks after it.
+    pub const OFFSET: f32 = 4.0;
+    /// How many vertices fit into a buffer, larger meshes get a buffer of their own
+    const PAGE_VERTICES: usize = 1 << 18;
+    /// The size of an index buffer, in bytes
+    const PAGE_INDEX_BYTES: usize = 1 << 21;
+
+    pub fn new() -> Self {
+        Self {
+            pages: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    /// Uploads a mesh, the positions are stored relative to `origin`
+    pub fn upload(&self, data: &MeshData, origin: Vec3) -> ArenaMesh {
+        self.upload_scaled(data, origin, 1.0)
+    }
+
+    /// Uploads a mesh which is drawn `scale` times larger, so meshes covering more than
+    /// `65536 / SCALE` blocks fit too. `origin` is in units of the mesh.
+    pub fn upload_scaled(&self, data: &MeshData, origin: Vec3, scale: f32) -> ArenaMesh {
+        let mut mesh = ArenaMesh {
+            arena: self.clone(),
+            page: None,
+            origin,
+            scale,
+            first_vertex: 0,
+            vertex_count: data.vertices.len(),
+            index_offset: 0,
+            index_bytes: 0,
+            count: (data.indices.len() * 3) as i32,
+            index_type: gl33::GL_UNSIGNED_SHORT,
+        };
+        if data.indices.is_empty() {
+            mesh.vertex_count = 0;
+            return mesh;
+        }
+
+        let vertices: Vec<CompactVertex> = (0..data.vertices.len())
+            .map(|i| CompactVertex::pack(data, i, origin))
+            .collect();
+        let short_indices: Vec<u16>;
+        let indices: &[u8] = if vertices.len() <= u16::MAX as usize + 1 {
+            short_indices = data
+                .indices
+                .iter()
+                .flat_map(|x| [x.0 as u16, x.1 as u16, x.2 as u16])
+                .collect();
+            bytes(&short_indices)
+        } else {
+            mesh.index_type = gl33::GL_UNSIGNED_INT;
+            bytes(&data.indices)
+        };
+        mesh.index_bytes = indices.len();
+
+        let mut pages = self.pages.borrow_mut();
+        let found = pages.iter_mut().enumerate().find_map(|(i, page)| {
+            let range = page.as_mut()?.alloc(vertices.len(), indices.len())?;
+            Some((i, range))
+        });
+        let (page_index, (first_vertex, index_offset)) = match found {
+            Some(x) => x,
+            None => {
+                let mut page = Page::new(
+                    Self::PAGE_VERTICES.max(vertices.len()),
+                    Self::PAGE_INDEX_BYTES.max(indices.len()),
+                );
+                let range = page.alloc(vertices.len(), indices.len()).unwrap();
+                // reuse the slot of a removed buffer, so the indices of the others stay the same
+                let i = match pages.iter().position(|x| x.is_none()) {
+                    Some(i) => i,
+                    None => {
+                        pages.push(None);
+                        pages.len() - 1
+                    }
+                };
+                pages[i] = Some(page);
+                (i, range)
+            }
+        };
+        let page = pages[page_index].as_ref().unwrap();
+        unsafe {
+            // the copy target doesn't change the buffers of the bound VAO
+            glBindBuffer(gl33::GL_COPY_WRITE_BUFFER, page.vertex_buffer);
+            glBufferSubData(
+                gl33::GL_COPY_WRITE_BUFFER,
+                (first_vertex * std::mem::size_of::<CompactVertex>()) as isize,
+                std::mem::size_of_val(vertices.as_slice()) as isize,
+                vertices.as_ptr() as *const std::ffi::c_void,
+            );
+            glBindBuffer(gl33::GL_COPY_WRITE_BUFFER, page.index_buffer);
+            glBufferSubData(
+                gl33::GL_COPY_WRITE_BUFFER,
+                index_offset as isize,
+                indices.len() as isize,
+                indices.as_ptr() as *const std::ffi::c_void,
+            );
+        }
+        mesh.page = Some(page_index);
+        mesh.first_vertex = first_vertex;
+        mesh.index_offset = index_offset;
+        mesh
+    }
+
+    /// Draws meshes of the arena, grouped by their buffers. `set_model` is called with the model
+    /// matrix of every mesh before it is drawn.
+    pub fn draw<'a>(
+        &self,
+        meshes: impl Iterator<Item = &'a ArenaMesh>,
+        set_model: impl FnMut(Mat4),
+    ) {
+        let mut meshes: Vec<&ArenaMesh> = meshes.collect();
+        meshes.sort_by_key(|x| x.page);
+        self.draw_in_order(meshes.into_iter(), set_model);
+    }
+
+    /// Draws meshes of the arena in the given order, for when they are blended with each other
+    pub fn draw_in_order<'a>(
+        &self,
+        meshes: impl Iterator<Item = &'a ArenaMesh>,
+        mut set_model: impl FnMut(Mat4),
+    ) {
+        let meshes = meshes.filter(|x| x.page.is_some());
+        let pages = self.pages.borrow();
+        let mut bound = None;
+        for mesh in meshes {
+            if bound != mesh.page {
+                bound = mesh.page;
+                glBindVertexArray(pages[mesh.page.unwrap()].as_ref().unwrap().vao);
+            }
+            set_model(mesh.model());
+            unsafe {
+                glDrawElementsBaseVertex(
+                    gl33::GL_TRIANGLES,
+                    mesh.count,
+                    mesh.index_type,
+                    mesh.index_offset as *const std::ffi::c_void,
+                    mesh.first_vertex as i32,
+                );
+            }
+        }
+    }
+
+    /// How many bytes of GPU memory are reserved by the buffers, used or not
+    pub fn capacity(&self) -> usize {
+        self.pages
+            .borrow()
+            .iter()
+            .flatten()
+            .map(|x| x.capacity())
+            .sum()
+    }
+}
+
+/// The bytes of a slice of plain numbers
+fn bytes<T: Copy>(data: &[T]) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, std::mem::size_of_val(data)) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A mesh of `n` vertices on a line along x, with one triangle
+    fn line(n: usize) -> MeshData {
+        let mut data = MeshData::new();
+        for i in 0..n {
+            data.vertices.push((i as f32 * 0.25, 1.0, 2.0));
+            data.texcoords.push((0.0, 1.0));
+            data.normals.push((0.0, 1.0, 0.0));
+        }
+        data.indices.push((0, 1, (n - 1) as i32));
+        data
+    }
+
+    /// The vertices of a mesh, read back from the fake buffer
+    fn uploaded(arena: &MeshArena, mesh: &ArenaMesh) -> Vec<CompactVertex> {
+        let buffer = arena.pages.borrow()[mesh.page.unwrap()]
+            .as_ref()
+            .unwrap()
+            .vertex_buffer;
+        let size = std::mem::size_of::<CompactVertex>();
+        fake_gl::STATE.with_borrow(|s| {
+            s.buffers[&buffer][mesh.first_vertex * size..][..mesh.vertex_count * size]
+                .chunks(size)
+                .map(|x| unsafe { std::ptr::read_unaligned(x.as_ptr() as *const CompactVertex) })
+                .collect()
+        })
+    }
+
+    #[test]
+    fn pack_stores_axis_normals_as_faces() {
+        assert_eq!(std::mem::size_of::<CompactVertex>(), 28);
+        let mut data = line(3);
+        data.normals[1] = (0.0, 0.0, -1.0);
+        data.normals[2] = (0.6, 0.8, 0.0);
+        let v: Vec<CompactVertex> = (0..3)
+            .map(|i| CompactVertex::pack(&data, i, Vec3::ZERO))
+            .collect();
+        assert_eq!((v[0].face, v[0].normal), (1, [0; 3]));
+        assert_eq!((v[1].face, v[1].normal), (6, [0; 3]));
+        // a normal which doesn't point along an axis needs the separate slot
+        assert_eq!((v[2].face, v[2].normal), (0, [76, 102, 0]));
+    }
+
+    #[test]
+    fn upload_scaled_keeps_the_positions() {
+        fake_gl::load();
+        let arena = MeshArena::new();
+        let data = line(5);
+        let origin = Vec3::new(-3.0, 0.5, 4.0);
+        for scale in [1.0, 4.0] {
+            let mesh = arena.upload_scaled(&data, origin, scale);
+            assert_eq!(mesh.vertex_count(), 5);
+            assert_eq!(mesh.index_type, gl33::GL_UNSIGNED_SHORT);
+            for (v, expected) in uploaded(&arena, &mesh).iter().zip(&data.vertices) {
+                let pos = Vec3::new(v.pos[0] as _, v.pos[1] as _, v.pos[2] as _);
+                let world = mesh.model().transform_point3(pos);
+                let expected = Vec3::new(expected.0, expected.1, expected.2) * scale;
+                assert!((world - expected).length() < 1e-3, "{} {}", world, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn empty_meshes_are_not_uploaded() {
+        fake_gl::load();
+        let arena = MeshArena::new();
+        let mesh = arena.upload(&MeshData::new(), Vec3::ZERO);
+        assert!(mesh.is_empty());
+        assert_eq!(mesh.memory_usage(), 0);
+        assert_eq!(arena.capacity(), 0);
+        arena.draw(std::iter::once(&mesh), |_| panic!("nothing to draw"));
+    }
+
+    #[test]
+    fn draw_groups_the_meshes_by_buffer() {
+        fake_gl::load();
+        let arena = MeshArena::new();
+        let a = arena.upload(&line(3), Vec3::ZERO);
+        // too large for the first buffer, so it gets its own with 32 bit indices
+        let b = arena.upload(&line(MeshArena::PAGE_VERTICES + 1), Vec3::ZERO);
+        let c = arena.upload(&line(4), Vec3::ZERO);
+        assert_eq!((a.page, b.page, c.page), (Some(0), Some(1), Some(0)));
+        assert_eq!(b.index_type, gl33::GL_UNSIGNED_INT);
+        assert_eq!((c.first_vertex, c.index_offset), (3, 8));
+
+        let vaos: Vec<u32> = arena
+            .pages
+            .borrow()
+            .iter()
+            .map(|x| x.as_ref().unwrap().vao)
+            .collect();
+        let draw = |mesh: &ArenaMesh| fake_gl::Draw {
+            vao: vaos[mesh.page.unwrap()],
+            count: 3,
+            index_type: mesh.index_type.0,
+            index_offset: mesh.index_offset,
+            first_vertex: mesh.first_vertex as i32,
+        };
+        let mut models = Vec::new();
+        fake_gl::STATE.with_borrow_mut(|s| s.binds.clear());
+        arena.draw([&a, &b, &c].into_iter(), |x| models.push(x));
+        fake_gl::STATE.with_borrow_mut(|s| {
+            assert_eq!(s.binds, [vaos[0], vaos[1]]);
+            assert_eq!(s.draws, [draw(&a), draw(&c), draw(&b)]);
+            s.binds.clear();
+            s.draws.clear();
+        });
+        assert_eq!(models, [a.model(), c.model(), b.model()]);
+
+        // blended meshes keep their order, even if that switches buffers more often
+        arena.draw_in_order([&a, &b, &c].into_iter(), |_| ());
+        fake_gl::STATE.with_borrow(|s| {
+            assert_eq!(s.binds, [vaos[0], vaos[1], vaos[0]]);
+            assert_eq!(s.draws, [draw(&a), draw(&b), draw(&c)]);
+        });
+
+        // the buffer of the large mesh goes away with it
+        let capacity = arena.capacity();
+        drop(b);
+        assert!(arena.capacity() < capacity);
+        assert!(fake_gl::STATE.with_borrow(|s| s.deleted.contains(&vaos[1])));
+    }
+
+    #[test]
+    fn alloc_aligns_and_fills_up() {
+        let mut a = RangeAllocator::new(16);
+        assert_eq!(a.alloc(3, 1), Some(0));
+        assert_eq!(a.alloc(4, 4), Some(4));
+        // the gap before the aligned range is still free
+        assert_eq!(a.alloc(1, 1), Some(3));
+        assert_eq!(a.alloc(8, 1), Some(8));
+        assert_eq!(a.alloc(1, 1), None);
+    }
+
+    #[test]
+    fn alloc_takes_the_smallest_free_range() {
+        let mut a = RangeAllocator::new(16);
+        assert_eq!(a.alloc(16, 1), Some(0));
+        a.free(0, 6);
+        a.free(10, 2);
+        assert_eq!(a.alloc(2, 1), Some(10));
+        assert_eq!(a.alloc(6, 1), Some(0));
+    }
+
+    #[test]
+    fn free_merges_neighbors() {
+        let mut a = RangeAllocator::new(12);
+        let ranges: Vec<usize> = (0..3).map(|_| a.alloc(4, 1).unwrap()).collect();
+        assert_eq!(ranges, [0, 4, 8]);
+        a.free(0, 4);
+        a.free(8, 4);
+        assert_eq!(a.free, [(0, 4), (8, 4)]);
+        assert!(!a.is_empty());
+        a.free(4, 4);
+        assert_eq!(a.free, [(0, 12)]);
+        assert!(a.is_empty());
+    }
+}
@@ -0,0 +1,166 @@
+//! The GL functions used by the arena, the models and the textures, faked so the tests don't need
+//! a window. Every thread has its own state, so the tests can run in parallel.
+
+use super::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::c_void;
+
+#[derive(Debug, PartialEq)]
+pub struct Draw {
+    pub vao: u32,
+    pub count: i32,
+    pub index_type: u32,
+    pub index_offset: usize,
+    pub first_vertex: i32,
+}
+
+#[derive(Default)]
+pub struct State {
+    next_id: u32,
+    bound_buffers: HashMap<u32, u32>,
+    pub buffers: HashMap<u32, Vec<u8>>,
+    pub vao: u32,
+    /// Every VAO which was bound, except for unbinding
+    pub binds: Vec<u32>,
+    pub draws: Vec<Draw>,
+    pub deleted: Vec<u32>,
+}
+
+thread_local! {
+    pub static STATE: RefCell<State> = RefCell::new(State::default());
+}
+
+extern "system" fn gen(n: i32, ids: *mut u32) {
+    STATE.with_borrow_mut(|s| {
+        for i in 0..n as usize {
+            s.next_id += 1;
+            unsafe { *ids.add(i) = s.next_id };
+        }
+    });
+}
+
+extern "system" fn delete(n: i32, ids: *const u32) {
+    STATE.with_borrow_mut(|s| {
+        for i in 0..n as usize {
+            let id = unsafe { *ids.add(i) };
+            s.buffers.remove(&id);
+            s.deleted.push(id);
+        }
+    });
+}
+
+extern "system" fn bind_vertex_array(vao: u32) {
+    STATE.with_borrow_mut(|s| {
+        s.vao = vao;
+        if vao != 0 {
+            s.binds.push(vao);
+        }
+    });
+}
+
+extern "system" fn bind_buffer(target: gl33::GLenum, buffer: u32) {
+    STATE.with_borrow_mut(|s| s.bound_buffers.insert(target.0, buffer));
+}
+
+extern "system" fn buffer_data(
+    target: gl33::GLenum,
+    size: isize,
+    _: *const c_void,
+    _: gl33::GLenum,
+) {
+    STATE.with_borrow_mut(|s| {
+        let buffer = s.bound_buffers[&target.0];
+        s.buffers.insert(buffer, vec![0; size as usize]);
+    });
+}
+
+extern "system" fn buffer_sub_data(
+    target: gl33::GLenum,
+    offset: isize,
+    size: isize,
+    data: *const c_void,
+) {
+    STATE.with_borrow_mut(|s| {
+        let buffer = s.bound_buffers[&target.0];
+        let data = unsafe { std::slice::from_raw_parts(data as *const u8, size as usize) };
+        s.buffers.get_mut(&buffer).unwrap()[offset as usize..][..size as usize]
+            .copy_from_slice(data);
+    });
+}
+
+extern "system" fn vertex_attrib_pointer(
+    _: u32,
+    _: i32,
+    _: gl33::GLenum,
+    _: u8,
+    _: i32,
+    _: *const c_void,
+) {
+}
+
+extern "system" fn enable_vertex_attrib_array(_: u32) {}
+
+extern "system" fn bind_texture(_: gl33::GLenum, _: u32) {}
+
+extern "system" fn tex_parameteri(_: gl33::GLenum, _: gl33::GLenum, _: i32) {}
+
+#[allow(clippy::too_many_arguments)]
+extern "system" fn tex_image_2d(
+    _: gl33::GLenum,
+    _: i32,
+    _: i32,
+    _: i32,
+    _: i32,
+    _: i32,
+    _: gl33::GLenum,
+    _: gl33::GLenum,
+    _: *const c_void,
+) {
+}
+
+extern "system" fn generate_mipmap(_: gl33::GLenum) {}
+
+extern "system" fn draw_elements_base_vertex(
+    _: gl33::GLenum,
+    count: i32,
+    index_type: gl33::GLenum,
+    offset: *const c_void,
+    first_vertex: i32,
+) {
+    STATE.with_borrow_mut(|s| {
+        let draw = Draw {
+            vao: s.vao,
+            count,
+            index_type: index_type.0,
+            index_offset: offset as usize,
+            first_vertex,
+        };
+        s.draws.push(draw);
+    });
+}
+
+/// Loads the fake functions and resets the state of this thread
+pub fn load() {
+    static LOAD: std::sync::Once = std::sync::Once::new();
+    LOAD.call_once(|| unsafe {
+        glGenVertexArrays_load_with(&|_| gen as *const c_void);
+        glGenBuffers_load_with(&|_| gen as *const c_void);
+        glDeleteVertexArrays_load_with(&|_| delete as *const c_void);
+        glDeleteBuffers_load_with(&|_| delete as *const c_void);
+        glBindVertexArray_load_with(&|_| bind_vertex_array as *const c_void);
+        glBindBuffer_load_with(&|_| bind_buffer as *const c_void);
+        glBufferData_load_with(&|_| buffer_data as *const c_void);
+        glBufferSubData_load_with(&|_| buffer_sub_data as *const c_void);
+        glVertexAttribPointer_load_with(&|_| vertex_attrib_pointer as *const c_void);
+        glEnableVertexAttribArray_load_with(&|_| enable_vertex_attrib_array as *const c_void);
+        glGenTextures_load_with(&|_| gen as *const c_void);
+        glDeleteTextures_load_with(&|_| delete as *const c_void);
+        glBindTexture_load_with(&|_| bind_texture as *const c_void);
+        glTexParameteri_load_with(&|_| tex_parameteri as *const c_void);
+        glTexImage2D_load_with(&|_| tex_image_2d as *const c_void);
+        glGenerateMipmap_load_with(&|_| generate_mipmap as *const c_void);
+        glDrawElementsBaseVertex_load_with(&|_| draw_elements_base_vertex as *const c_void);
+    });
+    STATE.with_borrow_mut(|s| *s = State::default());
+}
@@ -4,6 +4,14 @@ pub struct MeshData {
     pub indices: Vec<(i32, i32, i32)>,
     pub texcoords: Vec<(f32, f32)>,
     pub normals: Vec<(f32, f32, f32)>,
+    /// Multiplied with the texture, white if missing
+    pub colors: Vec<(f32, f32, f32)>,
+    /// Where a repeating texture is in the atlas: the texture coordinates are in units of 16 tiles
+    /// (so they stay between 0 and 1 for a chunk) and get wrapped into it. All zeros (or missing)
+    /// for the usual atlas texture coordinates.
+    pub tiles: Vec<(f32, f32, f32, f32)>,
+    /// Ambient occlusion, multiplied with the light. 1 (no occlusion) if missing
+    pub ao: Vec<f32>,
 }
 
 #[allow(unused)]
@@ -17,6 +25,16 @@ impl MeshData {
         }
         self.texcoords.append(&mut other.texcoords);
         self.normals.append(&mut other.normals);
+        self.colors.resize(index_offset as usize, (1.0, 1.0, 1.0));
+        self.colors.append(&mut other.colors);
+        self.colors.resize(self.vertices.len(), (1.0, 1.0, 1.0));
+        self.tiles
+            .resize(index_offset as usize, (0.0, 0.0, 0.0, 0.0));
+        self.tiles.append(&mut other.tiles);
+        self.tiles.resize(self.vertices.len(), (0.0, 0.0, 0.0, 0.0));
+        self.ao.resize(index_offset as usize, 1.0);
+        self.ao.append(&mut other.ao);
+        self.ao.resize(self.vertices.len(), 1.0);
     }
 
     pub fn translate(&mut self, pos: (f32, f32, f32)) {
@@ -27,12 +45,29 @@ impl MeshData {
         }
     }
 
+    /// How many bytes the vectors take up
+    pub fn memory_usage(&self) -> usize {
+        fn bytes<T>(x: &Vec<T>) -> usize {
+            x.capacity() * std::mem::size_of::<T>()
+        }
+        bytes(&self.vertices)
+            + bytes(&self.indices)
+            + bytes(&self.texcoords)
+            + bytes(&self.normals)
+            + bytes(&self.colors)
+            + bytes(&self.tiles)
+            + bytes(&self.ao)
+    }
+
     pub fn new() -> Self {
         MeshData {
             vertices: Vec::new(),
             indices: Vec::new(),
             texcoords: Vec::new(),
             normals: Vec::new(),
+            colors: Vec::new(),
+            tiles: Vec::new(),
+            ao: Vec::new(),
         }
     }
 
@@ -133,6 +168,9 @@ impl MeshData {
                 (0.0, -1.0, 0.0),
                 (0.0, -1.0, 0.0),
             ],
+            colors: Vec::new(),
+            tiles: Vec::new(),
+            ao: Vec::new(),
         }
     }
 }
@@ -1,8 +1,12 @@
+pub mod arena;
+#[cfg(test)]
+pub mod fake_gl;
 pub mod meshdata;
 pub mod model;
 pub mod shader;
 pub mod texture;
 
+pub use arena::{ArenaMesh, MeshArena};
 pub use gl33::global_loader::*;
 pub use meshdata::MeshData;
 pub use model::Model;
@@ -2,20 +2,15 @@ use super::*;
 
 pub struct Model {
     vao: u32,
-    vertices: u32,
-    indices: u32,
-    texcoords: u32,
-    normals: u32,
+    /// Every buffer of the model, deleted together with it
+    buffers: Vec<u32>,
     count: i32,
 }
 
 impl Drop for Model {
     fn drop(&mut self) {
         unsafe {
-            glDeleteBuffers(1, &self.vertices);
-            glDeleteBuffers(1, &self.indices);
-            glDeleteBuffers(1, &self.texcoords);
-            glDeleteBuffers(1, &self.normals);
+            glDeleteBuffers(self.buffers.len() as _, self.buffers.as_ptr());
             glDeleteVertexArrays(1, &self.vao);
         }
     }
@@ -29,19 +24,76 @@ impl Renderable for Model {
                 gl33::GL_TRIANGLES,
                 self.count,
                 gl33::GL_UNSIGNED_INT,
-                0 as *const _,
+                std::ptr::null(),
             );
         }
     }
 }
 
+/// Creates a buffer bound to `target` and fills it with `data`
+unsafe fn buffer<T>(target: gl33::GLenum, data: &[T]) -> u32 {
+    let mut buffer = 0;
+    glGenBuffers(1, &mut buffer);
+    assert_ne!(buffer, 0);
+    glBindBuffer(target, buffer);
+    glBufferData(
+        target,
+        std::mem::size_of_val(data) as isize,
+        data.as_ptr() as *const std::ffi::c_void,
+        gl33::GL_STATIC_DRAW,
+    );
+    buffer
+}
+
+/// Points a vertex attribute at the bound array buffer, integers are converted to floats from 0
+/// to 1 (or -1 to 1) if `normalized`
+pub(super) unsafe fn attribute(
+    index: u32,
+    size: i32,
+    kind: gl33::GLenum,
+    normalized: bool,
+    stride: usize,
+    offset: usize,
+) {
+    glVertexAttribPointer(
+        index,
+        size,
+        kind,
+        normalized as _,
+        stride as _,
+        offset as *const std::ffi::c_void,
+    );
+    glEnableVertexAttribArray(index);
+}
+
 impl Model {
     pub fn new(data: &MeshData) -> Self {
         let mut id: u32 = 0;
-        let mut vertices: u32 = 0;
-        let mut indices: u32 = 0;
-        let mut texcoords: u32 = 0;
-        let mut normals: u32 = 0;
+        // meshes without colors are white
+        let white;
+        let color_data = if data.colors.len() == data.vertices.len() {
+            &data.colors
+        } else {
+            white = vec![(1.0f32, 1.0f32, 1.0f32); data.vertices.len()];
+            &white
+        };
+        // and don't have repeating textures
+        let no_tiles;
+        let tile_data = if data.tiles.len() == data.vertices.len() {
+            &data.tiles
+        } else {
+            no_tiles = vec![(0.0f32, 0.0f32, 0.0f32, 0.0f32); data.vertices.len()];
+            &no_tiles
+        };
+        // and aren't occluded
+        let no_ao;
+        let ao_data = if data.ao.len() == data.vertices.len() {
+            &data.ao
+        } else {
+            no_ao = vec![1.0f32; data.vertices.len()];
+            &no_ao
+        };
+        let mut buffers = Vec::new();
         unsafe {
             // the VAO which will hold all of our VBOs
             glGenVertexArrays(1, &mut id);
@@ -49,84 +101,35 @@ impl Model {
             glBindVertexArray(id);
 
             // vertices
-            glGenBuffers(1, &mut vertices);
-            assert_ne!(vertices, 0);
-            glBindBuffer(gl33::GL_ARRAY_BUFFER, vertices);
-
-            glBufferData(
-                gl33::GL_ARRAY_BUFFER,
-                (data.vertices.len() * std::mem::size_of::<(f32, f32, f32)>()) as isize,
-                data.vertices.as_ptr() as *const std::ffi::c_void,
-                gl33::GL_STATIC_DRAW,
-            );
-            glVertexAttribPointer(
-                0,
-                3,
-                gl33::GL_FLOAT,
-                0,
-                std::mem::size_of::<(f32, f32, f32)>().try_into().unwrap(),
-                0 as *const _,
-            );
-            glEnableVertexAttribArray(0);
+            buffers.push(buffer(gl33::GL_ARRAY_BUFFER, &data.vertices));
+            attribute(0, 3, gl33::GL_FLOAT, false, 12, 0);
 
             // texture coordinates
-            glGenBuffers(1, &mut texcoords);
-            assert_ne!(texcoords, 0);
-            glBindBuffer(gl33::GL_ARRAY_BUFFER, texcoords);
-            glBufferData(
-                gl33::GL_ARRAY_BUFFER,
-                (data.texcoords.len() * std::mem::size_of::<(f32, f32)>()) as isize,
-                data.texcoords.as_ptr() as *const std::ffi::c_void,
-                gl33::GL_STATIC_DRAW,
-            );
-            glVertexAttribPointer(
-                1,
-                2,
-                gl33::GL_FLOAT,
-                0,
-                std::mem::size_of::<(f32, f32)>().try_into().unwrap(),
-                0 as *const _,
-            );
-            glEnableVertexAttribArray(1);
+            buffers.push(buffer(gl33::GL_ARRAY_BUFFER, &data.texcoords));
+            attribute(1, 2, gl33::GL_FLOAT, false, 8, 0);
 
             // normals
-            glGenBuffers(1, &mut normals);
-            assert_ne!(normals, 0);
-            glBindBuffer(gl33::GL_ARRAY_BUFFER, normals);
-            glBufferData(
-                gl33::GL_ARRAY_BUFFER,
-                (data.normals.len() * std::mem::size_of::<(f32, f32, f32)>()) as isize,
-                data.normals.as_ptr() as *const std::ffi::c_void,
-                gl33::GL_STATIC_DRAW,
-            );
-            glVertexAttribPointer(
-                2,
-                3,
-                gl33::GL_FLOAT,
-                0,
-                std::mem::size_of::<(f32, f32, f32)>().try_into().unwrap(),
-                0 as *const _,
-            );
-            glEnableVertexAttribArray(2);
+            buffers.push(buffer(gl33::GL_ARRAY_BUFFER, &data.normals));
+            attribute(2, 3, gl33::GL_FLOAT, false, 12, 0);
 
-            // indices
-            glGenBuffers(1, &mut indices);
-            assert_ne!(indices, 0);
-            glBindBuffer(gl33::GL_ELEMENT_ARRAY_BUFFER, indices);
+            // colors
+            buffers.push(buffer(gl33::GL_ARRAY_BUFFER, color_data));
+            attribute(3, 3, gl33::GL_FLOAT, false, 12, 0);
 
-            glBufferData(
-                gl33::GL_ELEMENT_ARRAY_BUFFER,
-                (data.indices.len() * std::mem::size_of::<(i32, i32, i32)>()) as isize,
-                data.indices.as_ptr() as *const std::ffi::c_void,
-                gl33::GL_STATIC_DRAW,
-            );
+            // tiles
+            buffers.push(buffer(gl33::GL_ARRAY_BUFFER, tile_data));
+            attribute(4, 4, gl33::GL_FLOAT, false, 16, 0);
+
+            // ambient occlusion
+            buffers.push(buffer(gl33::GL_ARRAY_BUFFER, ao_data));
+            attribute(5, 1, gl33::GL_FLOAT, false, 4, 0);
+
+            // indices
+            buffers.push(buffer(gl33::GL_ELEMENT_ARRAY_BUFFER, &data.indices));
         }
         Model {
             vao: id,
-            vertices,
-            indices,
-            texcoords,
-            normals,
+            buffers,
             count: (data.indices.len() * 3) as i32,
         }
     }
@@ -13,61 +13,62 @@ pub struct Shader {
     id: u32,
 }
 
-impl Shader {
-    pub unsafe fn new(vscode: &[u8], fscode: &[u8]) -> Self {
-        let vs = glCreateShader(gl33::GL_VERTEX_SHADER);
-        assert_ne!(vs, 0);
-        glShaderSource(
-            vs,
-            1,
-            &(vscode.as_ptr().cast()),
-            &(vscode.len().try_into().unwrap()),
-        );
-        glCompileShader(vs);
-        let mut success = 0;
-        glGetShaderiv(vs, gl33::GL_COMPILE_STATUS, &mut success);
-        if success == 0 {
-            let mut cstring: Vec<u8> = Vec::with_capacity(1024);
-            let mut len: i32 = 0;
-            glGetShaderInfoLog(vs, 1024, &mut len, cstring.as_mut_ptr().cast());
-            cstring.set_len(len.try_into().unwrap());
-            panic!("Vertex shader error: {}", String::from_utf8_lossy(&cstring));
-        }
+impl Drop for Shader {
+    fn drop(&mut self) {
+        glDeleteProgram(self.id);
+    }
+}
 
-        let fs = glCreateShader(gl33::GL_FRAGMENT_SHADER);
-        assert_ne!(fs, 0);
-        glShaderSource(
-            fs,
-            1,
-            &(fscode.as_ptr().cast()),
-            &(fscode.len().try_into().unwrap()),
-        );
-        glCompileShader(fs);
-        let mut success = 0;
-        glGetShaderiv(fs, gl33::GL_COMPILE_STATUS, &mut success);
-        if success == 0 {
-            let mut cstring: Vec<u8> = Vec::with_capacity(1024);
-            let mut len: i32 = 0;
-            glGetShaderInfoLog(fs, 1024, &mut len, cstring.as_mut_ptr().cast());
-            cstring.set_len(len.try_into().unwrap());
-            panic!(
-                "Fragment shader error: {}",
-                String::from_utf8_lossy(&cstring)
-            );
-        }
+/// Compiles one stage of a shader program, returns the error log if it fails
+unsafe fn compile(kind: gl33::GLenum, code: &[u8]) -> Result<u32, String> {
+    let shader = glCreateShader(kind);
+    assert_ne!(shader, 0);
+    glShaderSource(
+        shader,
+        1,
+        &(code.as_ptr().cast()),
+        &(code.len().try_into().unwrap()),
+    );
+    glCompileShader(shader);
+    let mut success = 0;
+    glGetShaderiv(shader, gl33::GL_COMPILE_STATUS, &mut success);
+    if success == 0 {
+        let mut cstring: Vec<u8> = Vec::with_capacity(1024);
+        let mut len: i32 = 0;
+        glGetShaderInfoLog(shader, 1024, &mut len, cstring.as_mut_ptr().cast());
+        cstring.set_len(len.try_into().unwrap());
+        glDeleteShader(shader);
+        return Err(String::from_utf8_lossy(&cstring).into_owned());
+    }
+    Ok(shader)
+}
+
+impl Shader {
+    /// Compiles and links a shader program, returns the error message if it fails
+    pub unsafe fn new(vscode: &[u8], fscode: &[u8]) -> Result<Self, String> {
+        let vs = compile(gl33::GL_VERTEX_SHADER, vscode)
+            .map_err(|e| format!("Vertex shader error: {}", e))?;
+        let fs = match compile(gl33::GL_FRAGMENT_SHADER, fscode) {
+            Ok(x) => x,
+            Err(e) => {
+                glDeleteShader(vs);
+                return Err(format!("Fragment shader error: {}", e));
+            }
+        };
         let program = glCreateProgram();
         glAttachShader(program, vs);
         glAttachShader(program, fs);
         glLinkProgram(program);
+        glDeleteShader(vs);
+        glDeleteShader(fs);
         let mut success = 0;
         glGetProgramiv(program, gl33::GL_LINK_STATUS, &mut success);
         if success == 0 {
-            panic!("Unable to link shader program");
+            glDeleteProgram(program);
+            return Err(String::from("Unable to link shader program"));
         }
-        glDeleteShader(vs);
-        glDeleteShader(fs);
 
-        Shader { id: program }
+        Ok(Shader { id: program })
     }
 
     pub fn create_uniform(&self, name: &str) -> i32 {
@@ -13,22 +13,33 @@ impl Drop for Texture {
 }
 
 impl Texture {
-    pub fn load(file: &str) -> Self {
-        let img = image::io::Reader::open(file).unwrap().decode().unwrap();
+    /// Uploads an image to the GPU, with at most `mip_levels` smaller versions of it
+    pub fn from_image(img: &image::RgbaImage, mip_levels: u32) -> Self {
         let mut id: u32 = 0;
+        // the pixels stay sharp up close, far away they blend between the mip levels
+        let min_filter = if mip_levels > 0 {
+            gl33::GL_NEAREST_MIPMAP_LINEAR
+        } else {
+            gl33::GL_NEAREST
+        };
         unsafe {
             glGenTextures(1, &mut id as _);
             glBindTexture(gl33::GL_TEXTURE_2D, id);
             glTexParameteri(
                 gl33::GL_TEXTURE_2D,
                 gl33::GL_TEXTURE_MIN_FILTER,
-                gl33::GL_NEAREST.0 as _,
+                min_filter.0 as _,
             );
             glTexParameteri(
                 gl33::GL_TEXTURE_2D,
                 gl33::GL_TEXTURE_MAG_FILTER,
                 gl33::GL_NEAREST.0 as _,
             );
+            glTexParameteri(
+                gl33::GL_TEXTURE_2D,
+                gl33::GL_TEXTURE_MAX_LEVEL,
+                mip_levels as _,
+            );
             glTexImage2D(
                 gl33::GL_TEXTURE_2D,
                 0,
@@ -36,27 +47,48 @@ impl Texture {
                 img.width() as _,
                 img.height() as _,
                 0,
-                match img {
-                    image::DynamicImage::ImageLuma8(_) => gl33::GL_RED,
-                    image::DynamicImage::ImageLumaA8(_) => gl33::GL_RG,
-                    image::DynamicImage::ImageRgb8(_) => gl33::GL_RGB,
-                    image::DynamicImage::ImageRgba8(_) => gl33::GL_RGBA,
-                    _ => panic!("Wrong image format: {:?}", img),
-                },
+                gl33::GL_RGBA,
                 gl33::GL_UNSIGNED_BYTE,
-                img.as_bytes().as_ptr() as _,
+                img.as_ptr() as _,
             );
             glGenerateMipmap(gl33::GL_TEXTURE_2D);
         }
         Texture { id }
     }
 
+    /// Replaces a part of a mip level of the texture, starting at the given pixel
+    pub fn update(&self, x: u32, y: u32, level: u32, img: &image::RgbaImage) {
+        unsafe {
+            glBindTexture(gl33::GL_TEXTURE_2D, self.id);
+            glTexSubImage2D(
+                gl33::GL_TEXTURE_2D,
+                level as _,
+                x as _,
+                y as _,
+                img.width() as _,
+                img.height() as _,
+                gl33::GL_RGBA,
+                gl33::GL_UNSIGNED_BYTE,
+                img.as_ptr() as _,
+            );
+        }
+    }
+
     pub fn bind(&self) {
         unsafe {
             glBindTexture(gl33::GL_TEXTURE_2D, self.id);
         }
     }
 
+    /// Binds the texture to another texture unit than the first one, which stays active
+    pub fn bind_to(&self, unit: u32) {
+        unsafe {
+            glActiveTexture(gl33::GLenum(gl33::GL_TEXTURE0.0 + unit));
+            glBindTexture(gl33::GL_TEXTURE_2D, self.id);
+            glActiveTexture(gl33::GL_TEXTURE0);
+        }
+    }
+
     #[allow(unused)]
     pub fn unbind(&self) {
         unsafe {
@@ -5,5 +5,14 @@ mod game;
 mod graphics;
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    // `--check-blocks [files...]` only validates the blocks files and exits
+    if let Some(i) = args.iter().position(|x| x == "--check-blocks") {
+        let mut files = args[i + 1..].to_vec();
+        if files.is_empty() {
+            files.push(String::from("blocks.json"));
+        }
+        std::process::exit(if game::check_blocks(&files) { 0 } else { 1 });
+    }
     game::start();
 }